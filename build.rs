@@ -0,0 +1,24 @@
+use std::io::Read;
+
+const SNAPSHOT: &str = "out/data/coverage.json.gz";
+
+fn main() {
+    println!("cargo:rerun-if-changed={SNAPSHOT}");
+    if std::env::var_os("CARGO_FEATURE_EMBEDDED_DATA").is_none() {
+        return;
+    }
+    // an empty snapshot would compile fine and then fail every call, so refuse to build with it
+    let mut json = String::new();
+    let ok = std::fs::read(SNAPSHOT).is_ok_and(|gz| {
+        flate2::read::GzDecoder::new(&gz[..])
+            .read_to_string(&mut json)
+            .is_ok()
+            && json.contains("\"blocks.json\"")
+    });
+    if !ok {
+        panic!(
+            "`embedded-data` needs a real snapshot in {SNAPSHOT}, but it's missing or empty - \
+             run `cargo run --release` to regenerate it"
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// How many search nodes to try before settling for the best cover found so far
+const BUDGET: usize = 50_000;
+
+/// Picks as few fonts as possible such that every list in `needs` contains at least one of them
+///
+/// Each list is ranked best first, which decides between covers of the same size. The search is
+/// exact unless it runs out of [`BUDGET`], in which case the result is at least as good as picking
+/// greedily.
+pub(crate) fn minimal_cover(needs: &[&[String]]) -> Vec<String> {
+    let mut fonts = vec![];
+    let mut ids = HashMap::new();
+    let sets = needs
+        .iter()
+        .filter(|n| !n.is_empty())
+        .map(|n| {
+            n.iter()
+                .map(|f| {
+                    *ids.entry(f.as_str()).or_insert_with(|| {
+                        fonts.push(f.as_str());
+                        fonts.len() - 1
+                    })
+                })
+                .collect_vec()
+        })
+        .unique_by(|s| s.iter().copied().sorted().collect_vec())
+        .sorted_by_key(|s| s.len())
+        .collect_vec();
+    // anything that covers a set also covers all of its supersets, so they can be dropped
+    let mut kept: Vec<Vec<usize>> = vec![];
+    for s in sets {
+        if !kept.iter().any(|k| k.iter().all(|f| s.contains(f))) {
+            kept.push(s);
+        }
+    }
+    let mut covers = vec![vec![]; fonts.len()];
+    for (i, s) in kept.iter().enumerate() {
+        for f in s {
+            covers[*f].push(i);
+        }
+    }
+    let mut search = Search {
+        best: greedy(&kept, &covers),
+        sets: kept,
+        covers,
+        count: vec![],
+        chosen: vec![],
+        nodes: 0,
+    };
+    search.count = vec![0; search.sets.len()];
+    search.run();
+    search.best.iter().map(|f| fonts[*f].to_string()).collect()
+}
+
+/// Repeatedly picks whichever font covers the most remaining sets, preferring fonts that are
+/// ranked first in more of them
+fn greedy(sets: &[Vec<usize>], covers: &[Vec<usize>]) -> Vec<usize> {
+    let mut covered = vec![false; sets.len()];
+    let mut chosen = vec![];
+    while covered.iter().any(|c| !c) {
+        let best = (0..covers.len())
+            .max_by_key(|f| {
+                let open = covers[*f].iter().filter(|s| !covered[**s]).collect_vec();
+                let firsts = open.iter().filter(|s| sets[***s][0] == *f).count();
+                (open.len(), firsts, std::cmp::Reverse(*f))
+            })
+            .unwrap();
+        for s in &covers[best] {
+            covered[*s] = true;
+        }
+        chosen.push(best);
+    }
+    chosen
+}
+
+struct Search {
+    sets: Vec<Vec<usize>>,
+    covers: Vec<Vec<usize>>,
+    /// how many chosen fonts cover each set
+    count: Vec<usize>,
+    chosen: Vec<usize>,
+    best: Vec<usize>,
+    nodes: usize,
+}
+
+impl Search {
+    fn run(&mut self) {
+        if self.nodes >= BUDGET {
+            return;
+        }
+        self.nodes += 1;
+        // branching on the set with the fewest options keeps the tree narrow
+        let Some(next) = (0..self.sets.len())
+            .filter(|s| self.count[*s] == 0)
+            .min_by_key(|s| self.sets[*s].len())
+        else {
+            if self.chosen.len() < self.best.len() {
+                self.best = self.chosen.clone();
+            }
+            return;
+        };
+        if self.chosen.len() + self.lower_bound() >= self.best.len() {
+            return;
+        }
+        for f in self.sets[next].clone() {
+            self.chosen.push(f);
+            for s in &self.covers[f] {
+                self.count[*s] += 1;
+            }
+            self.run();
+            for s in &self.covers[f] {
+                self.count[*s] -= 1;
+            }
+            self.chosen.pop();
+        }
+    }
+
+    /// Uncovered sets that share no fonts each need a font of their own
+    fn lower_bound(&self) -> usize {
+        let mut used = vec![false; self.covers.len()];
+        let mut bound = 0;
+        for s in (0..self.sets.len()).filter(|s| self.count[*s] == 0) {
+            if self.sets[s].iter().all(|f| !used[*f]) {
+                for f in &self.sets[s] {
+                    used[*f] = true;
+                }
+                bound += 1;
+            }
+        }
+        bound
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cover(needs: &[&[&str]]) -> Vec<String> {
+        let needs = needs
+            .iter()
+            .map(|n| n.iter().map(|f| f.to_string()).collect_vec())
+            .collect_vec();
+        minimal_cover(&needs.iter().map(|n| n.as_slice()).collect_vec())
+            .into_iter()
+            .sorted()
+            .collect()
+    }
+
+    #[test]
+    fn symbols_go_with_a_font_thats_needed_anyway() {
+        assert_eq!(
+            cover(&[&["Sans"], &["Sans Symbols", "Sans Math"], &["Sans Math"],]),
+            ["Sans", "Sans Math"]
+        );
+    }
+
+    #[test]
+    fn beats_greedy() {
+        let needs: &[&[&str]] = &[&["A", "B"], &["C", "D"], &["E", "A", "D"], &["E", "C"]];
+        let sets = [vec![0, 1], vec![2, 3], vec![4, 2], vec![4, 0, 3]];
+        let mut covers = vec![vec![]; 5];
+        for (i, s) in sets.iter().enumerate() {
+            for f in s {
+                covers[*f].push(i);
+            }
+        }
+        assert_eq!(greedy(&sets, &covers).len(), 3);
+        assert_eq!(cover(needs), ["A", "C"]);
+    }
+
+    #[test]
+    fn supersets_dont_matter() {
+        assert_eq!(cover(&[&["A"], &["A", "B"], &["B", "A", "C"]]), ["A"]);
+        assert_eq!(cover(&[]), Vec::<String>::new());
+        assert_eq!(cover(&[&[], &["A"]]), ["A"]);
+    }
+
+    #[test]
+    fn ties_go_to_the_better_ranked_font() {
+        assert_eq!(cover(&[&["Serif", "Sans"]]), ["Serif"]);
+        assert_eq!(
+            cover(&[&["Sans", "Serif"], &["Serif", "Sans"], &["Serif", "Mono"]]),
+            ["Serif"]
+        );
+        assert_eq!(cover(&[&["Sans", "Serif"], &["Sans", "Mono"]]), ["Sans"]);
+    }
+}
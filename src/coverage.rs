@@ -0,0 +1,301 @@
+use crate::{github, BlockEndpoints, NotoizeError};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, Read},
+    path::PathBuf,
+    sync::OnceLock,
+};
+
+/// Somewhere to read the [notofonts/overview](https://github.com/notofonts/overview) data from
+pub trait CoverageSource: Send + Sync {
+    /// Returns the contents of `path` relative to the root of the overview repo, e.g.
+    /// `blocks.json` or `blocks/block-001.json`
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError>;
+
+    /// Identifies the version of the data, e.g. a commit hash. Sources that return `None` can't
+    /// be cached by [`CachedCoverage`]
+    fn revision(&self) -> Option<String> {
+        None
+    }
+}
+
+/// Fetches straight from the overview repo on GitHub
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GithubCoverage;
+
+impl CoverageSource for GithubCoverage {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+        github::fetch("notofonts", "overview", path)
+    }
+
+    fn revision(&self) -> Option<String> {
+        github::latest_commit("notofonts", "overview").ok()
+    }
+}
+
+/// Reads from a checked-out copy of the overview repo
+#[derive(Debug, Clone)]
+pub struct LocalCoverage {
+    root: PathBuf,
+}
+
+impl LocalCoverage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl CoverageSource for LocalCoverage {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+        let path = self.root.join(path);
+        fs::read(&path).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => NotoizeError::MissingFile(path.display().to_string()),
+            _ => NotoizeError::Io(format!("{}: {e}", path.display())),
+        })
+    }
+}
+
+/// Keeps a copy of everything `inner` returns on disk, so that later clients and processes don't
+/// have to fetch it again
+///
+/// Files are stored under `<dir>/<revision>/`, so a new commit to the overview repo means a fresh
+/// download. If `inner` can't tell what revision it's at (e.g. when offline or rate limited), the
+/// newest revision in the cache gets used instead, and nothing new is written to it. If the cache
+/// is empty too, nothing is cached.
+#[derive(Debug)]
+pub struct CachedCoverage<S> {
+    inner: S,
+    dir: PathBuf,
+    revision: OnceLock<Option<Revision>>,
+}
+
+#[derive(Debug)]
+struct Revision {
+    name: String,
+    /// Whether it came from `inner` or was pinned, rather than being the newest one on disk
+    confirmed: bool,
+}
+
+impl<S: CoverageSource> CachedCoverage<S> {
+    pub fn new(inner: S, dir: impl Into<PathBuf>) -> Self {
+        Self {
+            inner,
+            dir: dir.into(),
+            revision: OnceLock::new(),
+        }
+    }
+
+    /// Caches in `$XDG_CACHE_HOME/notoize`, falling back to `~/.cache/notoize`
+    pub fn in_default_dir(inner: S) -> Self {
+        Self::new(inner, default_cache_dir())
+    }
+
+    /// Always uses the data for `revision`, without asking `inner` what the latest one is
+    pub fn pinned(self, revision: impl Into<String>) -> Self {
+        let _ = self.revision.set(Some(Revision {
+            name: revision.into(),
+            confirmed: true,
+        }));
+        self
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// Deletes everything in the cache
+    pub fn invalidate(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Deletes cached data for every revision except the current one. Does nothing if the current
+    /// revision isn't known for sure
+    pub fn prune(&self) -> io::Result<()> {
+        let current = match self.current_revision() {
+            Some(Revision {
+                name,
+                confirmed: true,
+            }) => name.as_str(),
+            _ => return Ok(()),
+        };
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy() != current {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+
+    fn current_revision(&self) -> Option<&Revision> {
+        self.revision
+            .get_or_init(|| match self.inner.revision() {
+                Some(name) => Some(Revision {
+                    name,
+                    confirmed: true,
+                }),
+                None => self.newest_cached().map(|name| Revision {
+                    name,
+                    confirmed: false,
+                }),
+            })
+            .as_ref()
+    }
+
+    /// The most recently written revision directory
+    fn newest_cached(&self) -> Option<String> {
+        fs::read_dir(&self.dir)
+            .ok()?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.path().is_dir())
+            .filter_map(|e| Some((e.metadata().ok()?.modified().ok()?, e.file_name())))
+            .max_by_key(|(modified, _)| *modified)
+            .map(|(_, name)| name.to_string_lossy().to_string())
+    }
+}
+
+impl<S: CoverageSource> CoverageSource for CachedCoverage<S> {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+        let Some(revision) = self.current_revision() else {
+            return self.inner.fetch(path);
+        };
+        let cached = self.dir.join(&revision.name).join(path);
+        if let Ok(bytes) = fs::read(&cached) {
+            return Ok(bytes);
+        }
+        let bytes = self.inner.fetch(path)?;
+        // fresh data doesn't belong under an old revision
+        if !revision.confirmed {
+            return Ok(bytes);
+        }
+        // failing to write the cache shouldn't stop anything
+        if let Some(parent) = cached.parent() {
+            let _ = fs::create_dir_all(parent).and_then(|_| fs::write(&cached, &bytes));
+        }
+        Ok(bytes)
+    }
+
+    fn revision(&self) -> Option<String> {
+        self.current_revision().map(|r| r.name.clone())
+    }
+}
+
+fn default_cache_dir() -> PathBuf {
+    env::var_os("XDG_CACHE_HOME")
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+        .unwrap_or_else(env::temp_dir)
+        .join("notoize")
+}
+
+/// `blocks.json` and every block's coverage, bundled into one gzipped JSON file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SnapshotCoverage {
+    revision: Option<String>,
+    files: HashMap<String, String>,
+}
+
+impl SnapshotCoverage {
+    /// Fetches everything from `source` and returns the compressed snapshot
+    pub fn build(source: &impl CoverageSource) -> Result<Vec<u8>, NotoizeError> {
+        let blocks = source.fetch("blocks.json")?;
+        let mut files = HashMap::new();
+        for block in serde_json::from_slice::<Vec<BlockEndpoints>>(&blocks)? {
+            eprintln!(
+                "\x1b[92mfetching\x1b[m {:04x}-{:04x} {}",
+                block.start, block.end, block.name
+            );
+            let path = format!("blocks/block-{:03}.json", block.ix);
+            let data = source.fetch(&path)?;
+            files.insert(
+                path,
+                String::from_utf8(data).map_err(|e| NotoizeError::Parse(e.to_string()))?,
+            );
+        }
+        files.insert(
+            "blocks.json".to_string(),
+            String::from_utf8(blocks).map_err(|e| NotoizeError::Parse(e.to_string()))?,
+        );
+        let snapshot = Self {
+            revision: source.revision(),
+            files,
+        };
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        serde_json::to_writer(&mut encoder, &snapshot)?;
+        encoder
+            .finish()
+            .map_err(|e| NotoizeError::Parse(e.to_string()))
+    }
+
+    /// Reads a snapshot made by [`SnapshotCoverage::build`]
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, NotoizeError> {
+        let mut json = vec![];
+        GzDecoder::new(bytes)
+            .read_to_end(&mut json)
+            .map_err(|e| NotoizeError::Parse(e.to_string()))?;
+        Ok(serde_json::from_slice(&json)?)
+    }
+}
+
+impl CoverageSource for SnapshotCoverage {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+        self.files
+            .get(path)
+            .map(|f| f.as_bytes().to_vec())
+            .ok_or_else(|| NotoizeError::MissingFile(format!("{path} in the snapshot")))
+    }
+
+    fn revision(&self) -> Option<String> {
+        self.revision.clone()
+    }
+}
+
+/// The snapshot compiled into the crate by the `embedded-data` feature, refreshed every quarter
+#[cfg(feature = "embedded-data")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbeddedCoverage;
+
+#[cfg(feature = "embedded-data")]
+static EMBEDDED: std::sync::LazyLock<Result<SnapshotCoverage, NotoizeError>> =
+    std::sync::LazyLock::new(|| {
+        SnapshotCoverage::from_bytes(include_bytes!("../out/data/coverage.json.gz"))
+    });
+
+#[cfg(feature = "embedded-data")]
+impl CoverageSource for EmbeddedCoverage {
+    fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+        EMBEDDED.as_ref().map_err(Clone::clone)?.fetch(path)
+    }
+
+    fn revision(&self) -> Option<String> {
+        EMBEDDED.as_ref().ok()?.revision()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_errors_arent_network_errors() {
+        let local = LocalCoverage::new(env!("CARGO_MANIFEST_DIR"));
+        assert!(matches!(
+            local.fetch("nonexistent.json"),
+            Err(NotoizeError::MissingFile(_))
+        ));
+        assert!(matches!(local.fetch("src"), Err(NotoizeError::Io(_))));
+        assert!(local.fetch("Cargo.toml").is_ok());
+    }
+}
@@ -0,0 +1,170 @@
+use crate::{FontFormat, FontSource, FontSpec, NotoEmojiSource, NotoizeError, Style};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
+use ttf_parser::{gsub::SubstitutionSubtable, GlyphId};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// The emoji sequences (ZWJ sequences, flags, keycaps, skin tones, ...) that a font draws as one
+/// glyph, read from its `GSUB` ligatures
+#[derive(Debug, Clone, Default)]
+pub struct EmojiSequences {
+    sequences: HashSet<Vec<u32>>,
+    codepoints: HashSet<u32>,
+}
+
+/// An emoji sequence that the font doesn't have, so it falls apart into `pieces`
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct BrokenSequence {
+    /// Byte range in the text
+    pub range: Range<usize>,
+    pub sequence: String,
+    /// What actually gets drawn, e.g. a man and a rocket for an unsupported astronaut
+    pub pieces: Vec<String>,
+}
+
+impl EmojiSequences {
+    /// Gets Noto Color Emoji from GitHub and reads its sequences
+    pub fn fetch() -> Result<Self, NotoizeError> {
+        eprintln!("\x1b[92mfetching\x1b[m Noto Color Emoji");
+        Self::from_font(&NotoEmojiSource.fetch(&FontSpec {
+            fontname: "Noto Color Emoji".to_string(),
+            filename: "NotoColorEmoji.ttf".to_string(),
+            style: Style::REGULAR,
+            variable: false,
+            format: FontFormat::default(),
+        })?)
+    }
+
+    /// Reads the sequences out of a font file, e.g. the `bytes` of a [`Font`](crate::Font)
+    pub fn from_font(bytes: &[u8]) -> Result<Self, NotoizeError> {
+        let face = ttf_parser::Face::parse(bytes, 0)
+            .map_err(|e| NotoizeError::Parse(format!("emoji font: {e}")))?;
+        let mut glyphs: HashMap<GlyphId, Vec<u32>> = HashMap::new();
+        let mut codepoints = HashSet::new();
+        for subtable in face.tables().cmap.iter().flat_map(|c| c.subtables) {
+            if !subtable.is_unicode() {
+                continue;
+            }
+            subtable.codepoints(|c| {
+                if let Some(g) = subtable.glyph_index(c) {
+                    glyphs.entry(g).or_insert_with(|| vec![c]);
+                    codepoints.insert(c);
+                }
+            });
+        }
+        let mut ligatures = vec![];
+        for lookup in face.tables().gsub.iter().flat_map(|g| g.lookups) {
+            for subtable in lookup.subtables.into_iter::<SubstitutionSubtable>() {
+                let SubstitutionSubtable::Ligature(lig) = subtable else {
+                    continue;
+                };
+                for first in (0..face.number_of_glyphs()).map(GlyphId) {
+                    let Some(set) = lig
+                        .coverage
+                        .get(first)
+                        .and_then(|i| lig.ligature_sets.get(i))
+                    else {
+                        continue;
+                    };
+                    for l in set {
+                        let input = std::iter::once(first).chain(l.components).collect_vec();
+                        ligatures.push((input, l.glyph));
+                    }
+                }
+            }
+        }
+        // ligatures can be built out of other ligatures, so keep going until nothing changes
+        let mut sequences = HashSet::new();
+        loop {
+            let before = sequences.len();
+            for (input, output) in &ligatures {
+                let Some(seq) = input
+                    .iter()
+                    .map(|g| glyphs.get(g).cloned())
+                    .collect::<Option<Vec<_>>>()
+                else {
+                    continue;
+                };
+                let seq = stripped(seq.into_iter().flatten());
+                glyphs.entry(*output).or_insert_with(|| seq.clone());
+                sequences.insert(seq);
+            }
+            if sequences.len() == before {
+                break;
+            }
+        }
+        Ok(Self {
+            sequences,
+            codepoints,
+        })
+    }
+
+    /// Whether `sequence` gets drawn as one glyph. Variation selectors don't matter
+    pub fn contains(&self, sequence: &str) -> bool {
+        let seq = stripped(sequence.chars().map(|c| c as u32));
+        match seq.len() {
+            0 => false,
+            1 => self.codepoints.contains(&seq[0]),
+            _ => self.sequences.contains(&seq),
+        }
+    }
+
+    /// The emoji sequences in `text` that will fall apart into separate glyphs
+    pub fn broken(&self, text: &str) -> Vec<BrokenSequence> {
+        text.grapheme_indices(true)
+            .filter(|(_, g)| {
+                let seq = stripped(g.chars().map(|c| c as u32));
+                seq.len() > 1
+                    && self.codepoints.contains(&seq[0])
+                    && seq.iter().any(|c| is_sequence_part(*c))
+                    && !self.sequences.contains(&seq)
+            })
+            .map(|(i, g)| BrokenSequence {
+                range: i..i + g.len(),
+                sequence: g.to_string(),
+                pieces: self.pieces(g),
+            })
+            .collect_vec()
+    }
+
+    /// Splits `cluster` into the longest sequences the font has, which is roughly what shaping
+    /// does
+    fn pieces(&self, cluster: &str) -> Vec<String> {
+        let chars = cluster.chars().collect_vec();
+        let mut pieces = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let end = (i + 1..=chars.len())
+                .rev()
+                .find(|end| {
+                    end - i > 1 && self.contains(&chars[i..*end].iter().collect::<String>())
+                })
+                .unwrap_or(i + 1);
+            let piece = chars[i..end].iter().collect::<String>();
+            // a lone zwj or variation selector doesn't draw anything
+            if !stripped(piece.chars().map(|c| c as u32)).is_empty()
+                && piece.chars().any(|c| c != '\u{200d}')
+            {
+                pieces.push(piece);
+            }
+            i = end;
+        }
+        pieces
+    }
+}
+
+/// Only turns up inside emoji sequences: zwj, regional indicators, the keycap, tags and skin tones
+fn is_sequence_part(c: u32) -> bool {
+    matches!(c, 0x200d | 0x20e3 | 0x1f1e6..=0x1f1ff | 0x1f3fb..=0x1f3ff | 0xe0020..=0xe007f)
+}
+
+/// Without variation selectors, which fonts don't always include in their ligatures
+fn stripped(seq: impl IntoIterator<Item = u32>) -> Vec<u32> {
+    seq.into_iter()
+        .filter(|c| *c != 0xfe0e && *c != 0xfe0f)
+        .collect_vec()
+}
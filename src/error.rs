@@ -0,0 +1,36 @@
+use std::fmt;
+
+/// Everything that can go wrong
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotoizeError {
+    /// A download failed, e.g. GitHub is down or timed out
+    Network(String),
+    /// Coverage data or a snapshot couldn't be parsed
+    Parse(String),
+    /// A font name that this version of the crate doesn't know about
+    UnknownFont(String),
+    /// None of the sources had a file
+    MissingFile(String),
+    /// A local file is there but couldn't be read, e.g. because of its permissions
+    Io(String),
+}
+
+impl fmt::Display for NotoizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Network(e) => write!(f, "network error: {e}"),
+            Self::Parse(e) => write!(f, "could not parse: {e}"),
+            Self::UnknownFont(e) => write!(f, "unknown font: {e}"),
+            Self::MissingFile(e) => write!(f, "could not find {e}"),
+            Self::Io(e) => write!(f, "could not read {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NotoizeError {}
+
+impl From<serde_json::Error> for NotoizeError {
+    fn from(e: serde_json::Error) -> Self {
+        Self::Parse(e.to_string())
+    }
+}
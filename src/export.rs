@@ -0,0 +1,246 @@
+use crate::{
+    family_name, filenames, CssOptions, FileOptions, FontDisplay, FontStack, NotoizeError, Style,
+};
+use itertools::Itertools;
+use std::path::Path;
+
+impl FontStack {
+    /// An `@font-face` rule for every font, each with a `unicode-range` of the characters it's
+    /// used for (including the ZWJs, variation selectors, ... next to them), then a `font-family`
+    /// list in stack order
+    pub fn to_css(&self, options: &CssOptions) -> Result<String, NotoizeError> {
+        let file_options = FileOptions::new().format(options.format);
+        let display = match options.font_display {
+            FontDisplay::Auto => "auto",
+            FontDisplay::Block => "block",
+            FontDisplay::Swap => "swap",
+            FontDisplay::Fallback => "fallback",
+            FontDisplay::Optional => "optional",
+        };
+        let prefix = match options.url_prefix.as_str() {
+            "" => String::new(),
+            p if p.ends_with('/') => p.to_string(),
+            p => format!("{p}/"),
+        };
+        let mut css = String::new();
+        for name in &self.names {
+            let Some(filename) = filenames(name, Style::REGULAR, &file_options)?
+                .into_iter()
+                .next()
+            else {
+                continue;
+            };
+            // cjk and emoji don't come in every format, so go by what the file actually is
+            let format = match filename.rsplit('.').next() {
+                Some("woff2") => "woff2",
+                Some("woff") => "woff",
+                Some("otf") => "opentype",
+                _ => "truetype",
+            };
+            let mut codepoints = self.codepoints_for(name);
+            codepoints.extend(
+                self.ignorables
+                    .iter()
+                    .filter(|(_, fonts)| fonts.contains(name))
+                    .map(|(c, _)| *c),
+            );
+            codepoints.sort_unstable();
+            let range = ranges(&codepoints)
+                .into_iter()
+                .map(|(a, b)| match a == b {
+                    true => format!("U+{a:04X}"),
+                    false => format!("U+{a:04X}-{b:04X}"),
+                })
+                .join(", ");
+            css += &format!(
+                "@font-face {{\n  font-family: \"{name}\";\n  src: url(\"{prefix}{filename}\") \
+                 format(\"{format}\");\n  font-display: {display};\n  unicode-range: {range};\n}}\n\n"
+            );
+        }
+        css += &format!(
+            "{} {{\n  font-family: {};\n}}\n",
+            options.selector,
+            self.names.iter().map(|n| format!("\"{n}\"")).join(", ")
+        );
+        Ok(css)
+    }
+
+    /// A fontconfig file that adds `dir` (wherever the fonts got written, e.g. `out/fonts`) and
+    /// adds the stack to `sans-serif`, `serif` and `monospace`. Fonts of the matching typeface go
+    /// before the system's own font for that generic, and the rest after it
+    pub fn to_fontconfig(&self, dir: impl AsRef<Path>) -> String {
+        let list = |tag: &str, names: &[&String]| match names.is_empty() {
+            true => String::new(),
+            false => format!(
+                "    <{tag}>\n{}    </{tag}>\n",
+                names
+                    .iter()
+                    .map(|n| format!("      <family>{}</family>\n", xml_escape(&family_name(n))))
+                    .join("")
+            ),
+        };
+        let aliases = [
+            ("sans-serif", "Sans"),
+            ("serif", "Serif"),
+            ("monospace", "Mono"),
+        ]
+        .iter()
+        .map(|(generic, typeface)| {
+            let (prefer, accept): (Vec<_>, Vec<_>) = self.names.iter().partition(|n| {
+                let words = n.split(' ').collect_vec();
+                words.contains(typeface) && (*typeface == "Mono" || !words.contains(&"Mono"))
+            });
+            format!(
+                "  <alias>\n    <family>{generic}</family>\n{}{}  </alias>\n",
+                list("prefer", &prefer),
+                list("accept", &accept)
+            )
+        })
+        .join("");
+        format!(
+            "<?xml version=\"1.0\"?>\n<!DOCTYPE fontconfig SYSTEM \"urn:fontconfig:fonts.dtd\">\n\
+             <fontconfig>\n  <dir>{}</dir>\n{aliases}</fontconfig>\n",
+            xml_escape(&dir.as_ref().display().to_string())
+        )
+    }
+
+    /// A Typst `#set text(font: ...)` rule with the stack as the fallback list
+    pub fn to_typst(&self) -> String {
+        format!(
+            "#set text(font: ({},))\n",
+            self.names
+                .iter()
+                .map(|n| format!("\"{}\"", family_name(n)))
+                .join(", ")
+        )
+    }
+
+    /// A LaTeX preamble for XeLaTeX: the first font in the stack is the main font, and every other
+    /// font gets a `\newfontfamily` and gets switched to by `ucharclasses` in the Unicode blocks
+    /// where it's used the most
+    pub fn to_latex(&self) -> String {
+        let Some(main) = self.names.first() else {
+            return String::new();
+        };
+        let mut latex = format!(
+            "\\usepackage{{fontspec}}\n\\usepackage{{ucharclasses}}\n\\setmainfont{{{}}}\n",
+            family_name(main)
+        );
+        for name in &self.names[1..] {
+            latex += &format!(
+                "\\newfontfamily\\{}{{{}}}\n",
+                latex_command(name),
+                family_name(name)
+            );
+        }
+        for block in &self.blocks {
+            let font = self
+                .assignment
+                .iter()
+                .filter(|(c, _)| block.start <= **c && **c <= block.end)
+                .map(|(_, f)| f)
+                .counts()
+                .into_iter()
+                // most used, then whichever's first in the stack
+                .max_by_key(|(f, n)| {
+                    (
+                        *n,
+                        std::cmp::Reverse(self.names.iter().position(|x| x == *f)),
+                    )
+                });
+            if let Some((font, _)) = font.filter(|(f, _)| *f != main) {
+                latex += &format!(
+                    "\\setTransitionsFor{{{}}}{{\\begingroup\\{}}}{{\\endgroup}}\n",
+                    ucharclass(&block.name),
+                    latex_command(font)
+                );
+            }
+        }
+        latex
+    }
+}
+
+/// The name `ucharclasses` uses for a block, e.g. `LatinSupplement` for `Latin-1 Supplement`
+fn ucharclass(block: &str) -> String {
+    block
+        .split([' ', '-', '_'])
+        .map(|w| {
+            let mut w = w.chars().filter(|c| c.is_ascii_alphabetic());
+            w.next()
+                .map(|f| f.to_ascii_uppercase().to_string() + &w.collect::<String>())
+                .unwrap_or_default()
+        })
+        .join("")
+}
+
+/// e.g. `notosansdevanagari` - control sequences can only have letters, so digits get spelled out
+/// to keep `Sans Symbols 2` apart from `Sans Symbols`
+fn latex_command(name: &str) -> String {
+    const DIGITS: [&str; 10] = [
+        "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+    ];
+    name.chars()
+        .filter_map(|c| match c.to_digit(10) {
+            Some(d) => Some(DIGITS[d as usize].to_string()),
+            None => c
+                .is_ascii_alphabetic()
+                .then(|| c.to_ascii_lowercase().to_string()),
+        })
+        .collect()
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Sorted codepoints as inclusive ranges of consecutive ones
+pub(crate) fn ranges(codepoints: &[u32]) -> Vec<(u32, u32)> {
+    let mut ranges: Vec<(u32, u32)> = vec![];
+    for c in codepoints {
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == *c => *end = *c,
+            _ => ranges.push((*c, *c)),
+        }
+    }
+    ranges
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn consecutive_codepoints_merge() {
+        assert_eq!(ranges(&[]), []);
+        assert_eq!(ranges(&[0x41]), [(0x41, 0x41)]);
+        assert_eq!(
+            ranges(&[0x41, 0x42, 0x43, 0x45, 0x1f600]),
+            [(0x41, 0x43), (0x45, 0x45), (0x1f600, 0x1f600)]
+        );
+    }
+
+    #[test]
+    fn css_ranges_have_the_ignorables_in_between() {
+        let css = crate::tests::client()
+            .notoize("A \u{1f468}\u{200d}\u{1f680}\u{fe0f}")
+            .unwrap()
+            .to_css(&CssOptions::new())
+            .unwrap();
+        assert!(css.contains("unicode-range: U+0041;"));
+        assert!(css.contains("unicode-range: U+200D, U+FE0F, U+1F468, U+1F680;"));
+    }
+
+    #[test]
+    fn css_ranges_leave_out_unrelated_ignorables() {
+        let css = crate::tests::client()
+            .notoize("\u{1f468}\u{200d}\u{1f680}\u{fe0f}A")
+            .unwrap()
+            .to_css(&CssOptions::new())
+            .unwrap();
+        assert!(css.contains("unicode-range: U+0041;"));
+        assert!(css.contains("unicode-range: U+200D, U+FE0F, U+1F468, U+1F680;"));
+    }
+}
@@ -0,0 +1,303 @@
+use crate::{github, NotoizeError};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::PathBuf, sync::Arc};
+
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+pub enum Weight {
+    Thin,
+    ExtraLight,
+    Light,
+    #[default]
+    Regular,
+    Medium,
+    SemiBold,
+    Bold,
+    ExtraBold,
+    Black,
+}
+
+/// A weight, and whether it's italic
+#[derive(
+    Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default,
+)]
+pub struct Style {
+    pub weight: Weight,
+    pub italic: bool,
+}
+
+impl Style {
+    pub const REGULAR: Self = Self::new(Weight::Regular, false);
+    pub const ITALIC: Self = Self::new(Weight::Regular, true);
+    pub const BOLD: Self = Self::new(Weight::Bold, false);
+    pub const BOLD_ITALIC: Self = Self::new(Weight::Bold, true);
+
+    pub const fn new(weight: Weight, italic: bool) -> Self {
+        Self { weight, italic }
+    }
+
+    /// The part of the filename after the `-`, e.g. `Regular`, `Italic` or `BoldItalic`
+    pub fn suffix(&self) -> String {
+        match (self.weight, self.italic) {
+            (Weight::Regular, false) => "Regular".to_string(),
+            (Weight::Regular, true) => "Italic".to_string(),
+            (w, i) => format!("{w:?}{}", if i { "Italic" } else { "" }),
+        }
+    }
+}
+
+/// A variation axis of a variable font
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Axis {
+    /// e.g. `wght`
+    pub tag: String,
+    pub min: f32,
+    pub default: f32,
+    pub max: f32,
+}
+
+impl Axis {
+    /// The axes in a font file's `fvar` table - static fonts and anything unparsable have none
+    pub fn read(bytes: &[u8]) -> Vec<Self> {
+        ttf_parser::Face::parse(bytes, 0)
+            .map(|face| {
+                face.variation_axes()
+                    .into_iter()
+                    .map(|a| Self {
+                        tag: a.tag.to_string(),
+                        min: a.min_value,
+                        default: a.def_value,
+                        max: a.max_value,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// The flavours of font file in notofonts.github.io
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum FontFormat {
+    #[default]
+    HintedTtf,
+    UnhintedTtf,
+    UnhintedOtf,
+    HintedWoff,
+    UnhintedWoff,
+    HintedWoff2,
+    UnhintedWoff2,
+}
+
+impl FontFormat {
+    pub fn hinted(&self) -> bool {
+        matches!(self, Self::HintedTtf | Self::HintedWoff | Self::HintedWoff2)
+    }
+
+    /// `ttf`, `otf`, `woff` or `woff2`
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::HintedTtf | Self::UnhintedTtf => "ttf",
+            Self::UnhintedOtf => "otf",
+            Self::HintedWoff | Self::UnhintedWoff => "woff",
+            Self::HintedWoff2 | Self::UnhintedWoff2 => "woff2",
+        }
+    }
+}
+
+/// A font file that a [`FontSource`] gets asked for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FontSpec {
+    /// e.g. `Noto Sans CJK JP`
+    pub fontname: String,
+    /// e.g. `NotoSansCJKjp-Regular.otf`
+    pub filename: String,
+    pub style: Style,
+    /// Whether this is a variable font, in which case only `style.italic` matters
+    pub variable: bool,
+    pub format: FontFormat,
+}
+
+impl FontSpec {
+    /// The directory name used by notofonts.github.io, e.g. `NotoSansAdlam`
+    pub fn family_dir(&self) -> &str {
+        self.filename
+            .split(['-', '.', '['])
+            .next()
+            .unwrap_or(&self.filename)
+    }
+}
+
+/// Somewhere to get font files from
+pub trait FontSource: Send + Sync {
+    /// Returns the bytes of `font`, or an error if this source doesn't have it
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError>;
+}
+
+/// One of the per-family directories in notofonts.github.io, e.g. `hinted/ttf`. Variable fonts
+/// are always unhinted
+fn notofonts_dir(font: &FontSpec) -> String {
+    let ext = font.format.extension();
+    match (font.variable, font.format.hinted()) {
+        (true, _) => format!("unhinted/variable-{ext}"),
+        (false, true) => format!("hinted/{ext}"),
+        (false, false) => format!("unhinted/{ext}"),
+    }
+}
+
+/// Fonts in any [`FontFormat`] from
+/// [notofonts.github.io](https://github.com/notofonts/notofonts.github.io)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotofontsSource;
+
+impl FontSource for NotofontsSource {
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+        let path = format!(
+            "fonts/{}/{}/{}",
+            font.family_dir(),
+            notofonts_dir(font),
+            font.filename
+        );
+        github::fetch("notofonts", "notofonts.github.io", &path)
+    }
+}
+
+/// Static OTFs, or variable fonts with every axis, from [noto-cjk](https://github.com/notofonts/noto-cjk)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotoCjkSource;
+
+impl FontSource for NotoCjkSource {
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+        if !font.fontname.contains("CJK") {
+            return Err(NotoizeError::MissingFile(format!(
+                "{} in noto-cjk",
+                font.fontname
+            )));
+        }
+        let words = font.fontname.split_ascii_whitespace().collect_vec();
+        let (Some(style), Some(var)) = (words.get(1), words.get(3).map(|v| v.to_lowercase()))
+        else {
+            return Err(NotoizeError::UnknownFont(font.fontname.clone()));
+        };
+        let dir = match var.as_str() {
+            "jp" => "Japanese",
+            "kr" => "Korean",
+            "sc" => "SimplifiedChinese",
+            "tc" => "TraditionalChinese",
+            "hk" => "TraditionalChineseHK",
+            _ => {
+                return Err(NotoizeError::UnknownFont(format!(
+                    "{} (unknown CJK variety `{var}`)",
+                    font.fontname
+                )))
+            }
+        };
+        let path = if font.variable {
+            let dir = font
+                .filename
+                .rsplit('.')
+                .next()
+                .unwrap_or_default()
+                .to_uppercase();
+            format!("{style}/Variable/{dir}/{}", font.filename)
+        } else {
+            format!("{style}/OTF/{dir}/{}", font.filename)
+        };
+        github::fetch("notofonts", "noto-cjk", &path)
+    }
+}
+
+/// Noto Color Emoji from [noto-emoji](https://github.com/googlefonts/noto-emoji)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NotoEmojiSource;
+
+impl FontSource for NotoEmojiSource {
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+        if !font.fontname.contains("Emoji") {
+            return Err(NotoizeError::MissingFile(format!(
+                "{} in noto-emoji",
+                font.fontname
+            )));
+        }
+        github::fetch(
+            "googlefonts",
+            "noto-emoji",
+            &format!("fonts/{}", font.filename),
+        )
+    }
+}
+
+/// Reads from a local directory, either flat (like `out/fonts`) or laid out like
+/// notofonts.github.io (`<dir>/NotoSansAdlam/hinted/ttf/NotoSansAdlam-Regular.ttf`, or
+/// `unhinted/variable-ttf` for variable fonts)
+#[derive(Debug, Clone)]
+pub struct LocalFonts {
+    root: PathBuf,
+}
+
+impl LocalFonts {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl FontSource for LocalFonts {
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+        let flat = self.root.join(&font.filename);
+        let nested = self
+            .root
+            .join(font.family_dir())
+            .join(notofonts_dir(font))
+            .join(&font.filename);
+        fs::read(&flat).or_else(|_| fs::read(&nested)).map_err(|_| {
+            NotoizeError::MissingFile(format!("{} in {}", font.filename, self.root.display()))
+        })
+    }
+}
+
+/// A chain of sources, tried in order until one of them has the font
+///
+/// The default chain is [`NotofontsSource`], then [`NotoCjkSource`], then [`NotoEmojiSource`].
+#[derive(Clone)]
+pub struct FontSources(Vec<Arc<dyn FontSource>>);
+
+impl Default for FontSources {
+    fn default() -> Self {
+        Self::new()
+            .with(NotofontsSource)
+            .with(NotoCjkSource)
+            .with(NotoEmojiSource)
+    }
+}
+
+impl FontSources {
+    /// An empty chain
+    pub fn new() -> Self {
+        Self(vec![])
+    }
+
+    /// Adds `source` to the end of the chain
+    pub fn with(mut self, source: impl FontSource + 'static) -> Self {
+        self.0.push(Arc::new(source));
+        self
+    }
+}
+
+impl FontSource for FontSources {
+    fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+        let mut missing = vec![];
+        let mut other = None;
+        for source in &self.0 {
+            match source.fetch(font) {
+                Ok(bytes) => return Ok(bytes),
+                Err(NotoizeError::MissingFile(f)) => missing.push(f),
+                Err(e) => {
+                    other.get_or_insert(e);
+                }
+            }
+        }
+        // a source being down is more interesting than the others not having the font
+        Err(other.unwrap_or_else(|| NotoizeError::MissingFile(missing.join(", "))))
+    }
+}
@@ -0,0 +1,55 @@
+use crate::NotoizeError;
+use reqwest::{blocking::Client, header::ACCEPT, StatusCode};
+use std::sync::LazyLock;
+
+static CLIENT: LazyLock<Result<Client, String>> = LazyLock::new(|| {
+    Client::builder()
+        .user_agent("notoize")
+        .build()
+        .map_err(|e| e.to_string())
+});
+
+fn client() -> Result<&'static Client, NotoizeError> {
+    CLIENT
+        .as_ref()
+        .map_err(|e| NotoizeError::Network(e.clone()))
+}
+
+/// Downloads `path` from the default branch of `user/repo`, retrying a few times
+pub(crate) fn fetch(user: &str, repo: &str, path: &str) -> Result<Vec<u8>, NotoizeError> {
+    let url = format!("https://raw.githubusercontent.com/{user}/{repo}/main/{path}");
+    let mut last_err = String::new();
+    for _ in 0..4 {
+        match client()?.get(&url).send() {
+            Ok(res) if res.status() == StatusCode::NOT_FOUND => {
+                return Err(NotoizeError::MissingFile(format!("{user}/{repo}/{path}")))
+            }
+            Ok(res) => match res.error_for_status().and_then(|r| r.bytes()) {
+                Ok(bytes) => return Ok(bytes.to_vec()),
+                Err(e) => last_err = e.to_string(),
+            },
+            Err(e) => last_err = e.to_string(),
+        }
+    }
+    Err(NotoizeError::Network(format!(
+        "multiple requests to {url} failed, the last with: {last_err}"
+    )))
+}
+
+/// The hash of the latest commit on the default branch of `user/repo`
+pub(crate) fn latest_commit(user: &str, repo: &str) -> Result<String, NotoizeError> {
+    let sha = client()?
+        .get(format!(
+            "https://api.github.com/repos/{user}/{repo}/commits/main"
+        ))
+        .header(ACCEPT, "application/vnd.github.sha")
+        .send()
+        .and_then(|r| r.error_for_status())
+        .and_then(|r| r.text())
+        .map_err(|e| NotoizeError::Network(e.to_string()))?;
+    if !sha.is_empty() && sha.chars().all(|c| c.is_ascii_hexdigit()) {
+        Ok(sha)
+    } else {
+        Err(NotoizeError::Parse(format!("`{sha}` is not a commit hash")))
+    }
+}
@@ -0,0 +1,140 @@
+use crate::{ignorable, FontStack};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+use unicode_script::{ScriptExtension, UnicodeScript};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// A stretch of text that gets drawn with one font
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Run {
+    /// Byte range in the text
+    pub range: Range<usize>,
+    /// `None` if nothing covers it
+    pub font: Option<String>,
+}
+
+impl FontStack {
+    /// Splits `text` into runs of one font each. Text only breaks between grapheme clusters, so
+    /// combining marks stay with their base, and characters shared between scripts (spaces,
+    /// punctuation, digits, ...) stay in the run around them if its font has them. Should be the
+    /// same text that went into [`notoize`](crate::NotoizeClient::notoize)
+    pub fn itemize(&self, text: &str) -> Vec<Run> {
+        let mut runs: Vec<Run> = vec![];
+        // the scripts that everything in the last run so far has in common
+        let mut ext = ScriptExtension::default();
+        // whether the last run has anything but ignorables in it, i.e. whether its font means
+        // anything yet
+        let mut decided = false;
+        for (i, cluster) in text.grapheme_indices(true) {
+            let range = i..i + cluster.len();
+            let chars = cluster
+                .chars()
+                .filter(|c| !ignorable(*c as u32))
+                .collect::<String>();
+            let Some(base) = chars.chars().next() else {
+                // nothing to draw, so it goes with whatever's before it
+                match runs.last_mut() {
+                    Some(run) => run.range.end = range.end,
+                    None => runs.push(Run { range, font: None }),
+                }
+                continue;
+            };
+            let cluster_ext = ScriptExtension::for_str(&chars);
+            if let Some(run) = runs.last_mut() {
+                if !decided {
+                    run.range.end = range.end;
+                    run.font = self.font_for(base).map(|f| f.to_string());
+                    ext = cluster_ext;
+                    decided = true;
+                    continue;
+                }
+                let joint = ext.intersection(cluster_ext);
+                if !joint.is_empty() && chars.chars().all(|c| self.fits(run.font.as_deref(), c)) {
+                    run.range.end = range.end;
+                    ext = joint;
+                    continue;
+                }
+            }
+            runs.push(Run {
+                range,
+                font: self.font_for(base).map(|f| f.to_string()),
+            });
+            ext = match cluster_ext.is_empty() {
+                true => base.script_extension(),
+                false => cluster_ext,
+            };
+            decided = true;
+        }
+        runs
+    }
+
+    /// Whether `c` can go in a run of `font`
+    fn fits(&self, font: Option<&str>, c: char) -> bool {
+        match font {
+            Some(font) => self.map.get(&(c as u32)).is_some_and(|fonts| {
+                fonts
+                    .iter()
+                    .any(|f| font.strip_prefix("Noto ") == Some(f.as_str()))
+            }),
+            None => self.font_for(c).is_none(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::client;
+
+    fn runs(text: &str) -> Vec<(std::ops::Range<usize>, Option<String>)> {
+        client()
+            .notoize(text)
+            .unwrap()
+            .itemize(text)
+            .into_iter()
+            .map(|r| (r.range, r.font))
+            .collect()
+    }
+
+    fn font(name: &str) -> Option<String> {
+        Some(name.to_string())
+    }
+
+    #[test]
+    fn emoji_sequences_get_their_own_run() {
+        assert_eq!(
+            runs("A\u{1f468}\u{200d}\u{1f680}A"),
+            [
+                (0..1, font("Noto Sans")),
+                (1..12, font("Noto Color Emoji")),
+                (12..13, font("Noto Sans")),
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_ignorables_join_the_first_run() {
+        assert_eq!(runs("\u{200d}A"), [(0..4, font("Noto Sans"))]);
+        assert_eq!(runs("\u{200d}"), [(0..3, None)]);
+    }
+
+    #[test]
+    fn combining_marks_stay_with_their_base() {
+        assert_eq!(runs("AA\u{301}A"), [(0..5, font("Noto Sans"))]);
+        assert_eq!(
+            runs("\u{1f468}\u{301}A"),
+            [(0..6, font("Noto Color Emoji")), (6..7, font("Noto Sans"))]
+        );
+    }
+
+    #[test]
+    fn uncovered_text_has_no_font() {
+        assert_eq!(
+            runs("ABBA"),
+            [
+                (0..1, font("Noto Sans")),
+                (1..3, None),
+                (3..4, font("Noto Sans")),
+            ]
+        );
+    }
+}
@@ -0,0 +1,201 @@
+use crate::script;
+use itertools::Itertools;
+use std::sync::LazyLock;
+
+/// Which variant of a script each language wants, keyed by BCP 47 tags. A key matches a tag if
+/// the tag has all of its subtags, and more specific keys win
+static LANGUAGE_VARIANTS: LazyLock<[(&str, &str); 27]> = LazyLock::new(|| {
+    [
+        ("ur", "Nastaliq Urdu"),
+        ("pa-Arab", "Nastaliq Urdu"),
+        ("pa-PK", "Nastaliq Urdu"),
+        ("ks", "Nastaliq Urdu"),
+        ("skr", "Nastaliq Urdu"),
+        ("lad-Hebr", "Rashi Hebrew"),
+        ("aii", "Sans Syriac Eastern"),
+        ("cld", "Sans Syriac Eastern"),
+        ("tru", "Sans Syriac Western"),
+        ("taq", "Sans Tifinagh Adrar"),
+        ("thv", "Sans Tifinagh Ahaggar"),
+        ("thv-LY", "Sans Tifinagh Ghat"),
+        ("thz", "Sans Tifinagh Air"),
+        ("ttq", "Sans Tifinagh Tawellemmet"),
+        ("kab-Tfng", "Sans Tifinagh Agraw Imazighen"),
+        ("ja", "Sans CJK JP"),
+        ("ko", "Sans CJK KR"),
+        ("zh", "Sans CJK SC"),
+        ("zh-Hans", "Sans CJK SC"),
+        ("zh-Hant", "Sans CJK TC"),
+        ("zh-TW", "Sans CJK TC"),
+        ("zh-HK", "Sans CJK HK"),
+        ("zh-MO", "Sans CJK HK"),
+        ("zh-Hant-HK", "Sans CJK HK"),
+        ("zh-Hant-MO", "Sans CJK HK"),
+        ("yue", "Sans CJK HK"),
+        ("yue-Hans", "Sans CJK SC"),
+    ]
+});
+
+/// The built-in language → variant table, as `(BCP 47 tag, font)` pairs
+pub fn language_variants() -> Vec<(String, String)> {
+    LANGUAGE_VARIANTS
+        .iter()
+        .map(|(t, f)| (t.to_string(), f.to_string()))
+        .collect_vec()
+}
+
+/// The parts of a BCP 47 tag that matter here
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct LanguageTag {
+    language: String,
+    script: Option<String>,
+    region: Option<String>,
+}
+
+impl LanguageTag {
+    fn parse(tag: &str) -> Self {
+        let mut subtags = tag.split(['-', '_']);
+        let language = subtags.next().unwrap_or_default().to_ascii_lowercase();
+        let mut script = None;
+        let mut region = None;
+        for s in subtags {
+            // extensions and private use, e.g. the `u-ca-chinese` in `zh-Hant-u-ca-chinese`
+            if s.len() == 1 {
+                break;
+            }
+            if s.len() == 4 && s.chars().all(|c| c.is_ascii_alphabetic()) && script.is_none() {
+                script = Some(s[..1].to_ascii_uppercase() + &s[1..].to_ascii_lowercase());
+            } else if (s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic())
+                || s.len() == 3 && s.chars().all(|c| c.is_ascii_digit()))
+                && region.is_none()
+            {
+                region = Some(s.to_ascii_uppercase());
+            }
+        }
+        Self {
+            language,
+            script,
+            region,
+        }
+    }
+
+    /// `None` if `pattern` doesn't match, otherwise how specific it is
+    fn specificity(&self, pattern: &Self) -> Option<usize> {
+        let parts = [
+            (&pattern.script, &self.script),
+            (&pattern.region, &self.region),
+        ];
+        (pattern.language == self.language && parts.iter().all(|(p, s)| p.is_none() || p == s))
+            .then(|| 1 + parts.iter().filter(|(p, _)| p.is_some()).count())
+    }
+}
+
+/// The preferred font for each script for `tag`, without the `Noto ` prefix. `extra` entries go
+/// after the built-in ones, so they win ties
+pub(crate) fn preferred_variants(tag: &str, extra: &[(String, String)]) -> Vec<String> {
+    let tag = LanguageTag::parse(tag);
+    LANGUAGE_VARIANTS
+        .iter()
+        .copied()
+        .chain(extra.iter().map(|(t, f)| (t.as_str(), f.as_str())))
+        .filter_map(|(pattern, font)| {
+            tag.specificity(&LanguageTag::parse(pattern))
+                .map(|s| (s, font.to_string()))
+        })
+        // later entries win ties
+        .rev()
+        .sorted_by_key(|(s, _)| std::cmp::Reverse(*s))
+        .unique_by(|(_, f)| script(f))
+        .map(|(_, f)| f)
+        .collect_vec()
+}
+
+/// Guesses CJK variants from the text itself, for when there's no language to go on
+pub(crate) fn guessed_variants(codepoints: &[u32]) -> Vec<String> {
+    let has = |ranges: &[(u32, u32)]| {
+        codepoints
+            .iter()
+            .any(|c| ranges.iter().any(|(a, b)| a <= c && c <= b))
+    };
+    // kana
+    if has(&[(0x3040, 0x30ff), (0x31f0, 0x31ff), (0xff66, 0xff9f)]) {
+        vec!["Sans CJK JP".to_string()]
+    // hangul
+    } else if has(&[
+        (0x1100, 0x11ff),
+        (0x3130, 0x318f),
+        (0xa960, 0xa97f),
+        (0xac00, 0xd7ff),
+    ]) {
+        vec!["Sans CJK KR".to_string()]
+    // bopomofo
+    } else if has(&[(0x3100, 0x312f), (0x31a0, 0x31bf)]) {
+        vec!["Sans CJK TC".to_string()]
+    } else {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first(tag: &str, extra: &[(&str, &str)]) -> Option<String> {
+        let extra = extra
+            .iter()
+            .map(|(t, f)| (t.to_string(), f.to_string()))
+            .collect_vec();
+        preferred_variants(tag, &extra).into_iter().next()
+    }
+
+    #[test]
+    fn parses_tags() {
+        assert_eq!(
+            LanguageTag::parse("zh_hant_hk"),
+            LanguageTag {
+                language: "zh".to_string(),
+                script: Some("Hant".to_string()),
+                region: Some("HK".to_string()),
+            }
+        );
+        assert_eq!(LanguageTag::parse("es-419").region.as_deref(), Some("419"));
+    }
+
+    #[test]
+    fn extensions_arent_regions() {
+        let tag = LanguageTag::parse("zh-Hant-u-ca-chinese");
+        assert_eq!(tag.script.as_deref(), Some("Hant"));
+        assert_eq!(tag.region, None);
+        assert_eq!(LanguageTag::parse("zh-x-hk").region, None);
+    }
+
+    #[test]
+    fn more_specific_entries_win() {
+        assert_eq!(first("zh-Hant-HK", &[]).as_deref(), Some("Sans CJK HK"));
+        assert_eq!(first("zh-Hant", &[]).as_deref(), Some("Sans CJK TC"));
+        assert_eq!(first("zh-CN", &[]).as_deref(), Some("Sans CJK SC"));
+        assert_eq!(first("yue", &[]).as_deref(), Some("Sans CJK HK"));
+        assert_eq!(first("yue-Hans", &[]).as_deref(), Some("Sans CJK SC"));
+        assert_eq!(first("en", &[]), None);
+    }
+
+    #[test]
+    fn extra_entries_override_built_in_ones() {
+        let extra = [("zh-Hant", "Sans CJK JP")];
+        assert_eq!(first("zh-Hant", &extra).as_deref(), Some("Sans CJK JP"));
+        // but not more specific built-in ones
+        assert_eq!(first("zh-Hant-HK", &extra).as_deref(), Some("Sans CJK HK"));
+        assert_eq!(
+            first("pa-Arab", &[("pa", "Sans Arabic")]).as_deref(),
+            Some("Nastaliq Urdu")
+        );
+    }
+
+    #[test]
+    fn variant_overrides_built_in_entries() {
+        let options = crate::NotoizeOptions::new()
+            .lang("yue")
+            .variant("yue", "Noto Sans CJK TC");
+        assert_eq!(options.preferred(&[]), ["Sans CJK TC"]);
+    }
+}
@@ -1,22 +1,67 @@
-use gh_file_curler::{fetch, wrapped_first};
 use itertools::Itertools;
-use serde::Deserialize;
-use std::{collections::HashMap, sync::LazyLock};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, LazyLock},
+};
+use unicode_script::{Script as Ucd, ScriptExtension, UnicodeScript};
 
-#[derive(Debug, Clone)]
+mod cover;
+mod coverage;
+mod emoji;
+mod error;
+mod export;
+mod fonts;
+mod github;
+mod itemize;
+mod lang;
+mod options;
+mod report;
+pub use coverage::*;
+pub use emoji::*;
+pub use error::*;
+pub use fonts::*;
+pub use itemize::*;
+pub use lang::language_variants;
+pub use options::*;
+pub use report::*;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct FontStack {
     pub names: Vec<String>,
+    /// Every codepoint the client has loaded so far, with its usable fonts best first
+    #[serde(serialize_with = "sorted")]
     pub map: HashMap<u32, Vec<String>>,
+    /// Same as `map`, but only for the codepoints in the input
+    #[serde(serialize_with = "sorted")]
+    pub input_map: HashMap<u32, Vec<String>>,
+    /// Which of `names` each codepoint in the input gets rendered with
+    #[serde(serialize_with = "sorted")]
+    assignment: HashMap<u32, String>,
+    /// Default-ignorable characters in the input (ZWJ, variation selectors, tags, ...), with the
+    /// fonts of the characters they go with
+    #[serde(default, serialize_with = "sorted")]
+    ignorables: HashMap<u32, Vec<String>>,
+    uncovered: Vec<u32>,
+    /// Codepoints in the input that some Noto font covers, but none that the options allow
+    #[serde(default)]
+    excluded: Vec<u32>,
+    blocks: Vec<BlockEndpoints>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Font {
     pub filename: String,
     pub fontname: String,
+    pub style: Style,
+    /// Empty unless it's a variable font
+    pub axes: Vec<Axis>,
+    /// Not serialized, so it's empty after deserializing
+    #[serde(skip)]
     pub bytes: Vec<u8>,
 }
 
-#[derive(Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct MapString {
     pub all: String,
     pub conflicts: String,
@@ -70,109 +115,249 @@ static SPECIAL_FILENAMES: LazyLock<[(&str, &str); 10]> = LazyLock::new(|| {
     ]
 });
 
-impl FontStack {
-    pub fn files(&self) -> Vec<Font> {
-        self.names
+/// What [`FontStack::files_with`] got
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FontFiles {
+    pub fonts: Vec<Font>,
+    /// Styles that a font doesn't come in, e.g. `Noto Sans Adlam` in italic
+    pub missing: Vec<(String, Style)>,
+}
+
+/// The filenames that Noto might use for `name` in `style`, best guess first. Variable fonts have
+/// their axes in the name, so there's more than one guess
+fn filenames(name: &str, style: Style, options: &FileOptions) -> Result<Vec<String>, NotoizeError> {
+    let suffix = style.suffix();
+    let variable = options.variable;
+    let (stem, ext) = if name.contains("CJK") {
+        let words = name.split_ascii_whitespace().collect_vec();
+        let (Some(typeface), Some(var)) = (words.get(1), words.get(3)) else {
+            return Err(NotoizeError::UnknownFont(name.to_string()));
+        };
+        // noto-cjk has no italics at all
+        if style.italic {
+            return Ok(vec![]);
+        }
+        if variable {
+            // noto-cjk only has variable fonts in these three
+            let ext = match options.format.extension() {
+                "ttf" => "ttf",
+                "woff2" => "woff2",
+                _ => "otf",
+            };
+            return Ok(vec![format!(
+                "Noto{typeface}CJK{}-VF.{ext}",
+                var.to_lowercase()
+            )]);
+        }
+        // and its static fonts only come as OTFs
+        (format!("Noto{typeface}CJK{}", var.to_lowercase()), "otf")
+    } else if SPECIAL_NAMES.contains(&name) {
+        let filename = SPECIAL_FILENAMES
             .iter()
-            .map(|x| {
-                let f = if x.contains("CJK") {
-                    let words = x.split_ascii_whitespace().collect_vec();
-                    format!("Noto{}CJK{}-Regular.otf", words[1], words[3].to_lowercase())
-                } else if SPECIAL_NAMES.contains(&x.as_str()) {
-                    SPECIAL_FILENAMES
-                        .iter()
-                        .find(|(name, _)| *name == x.as_str())
-                        .map(|(_, filename)| filename.to_string())
-                        .unwrap()
-                        .to_string()
-                } else {
-                    format!("{}-Regular.ttf", x.replace([' ', '-'], ""))
-                };
-                eprintln!("\x1b[92mfetching\x1b[m {x}");
-                Font {
-                    filename: f.clone(),
-                    fontname: x.to_string(),
-                    bytes: {
-                        let path = format!("fonts/{}/hinted/ttf/{f}", f.split('-').next().unwrap());
-                        wrapped_first(fetch("notofonts", "notofonts.github.io", &[&path]))
-                    }
-                    .unwrap_or_else(|e| {
-                        if x.contains("CJK") {
-                            let words = x.split_ascii_whitespace().collect_vec();
-                            wrapped_first(fetch(
-                                "notofonts",
-                                "noto-cjk",
-                                &[&format!("{}/OTF/{}/{f}", words[1], {
-                                    let var = words[3].to_lowercase();
-                                    match var.as_str() {
-                                        "jp" => "Japanese",
-                                        "kr" => "Korean",
-                                        "sc" => "SimplifiedChinese",
-                                        "tc" => "TraditionalChinese",
-                                        "hk" => "TraditionalChineseHK",
-                                        _ => {
-                                            panic!("unknown CJK variety `\x1b[91m{var}\x1b[m`")
-                                        }
-                                    }
-                                })],
-                            ))
-                            .unwrap()
-                        } else if x.contains("Emoji") {
-                            wrapped_first(fetch(
-                                "googlefonts",
-                                "noto-emoji",
-                                &["fonts/NotoColorEmoji.ttf"],
-                            ))
-                            .unwrap()
-                        } else {
-                            panic!(
-                                "could not find \x1b[91m{x}\x1b[m. The err from gh-file-curler \
-                                 is:\n    {e}"
-                            );
-                        }
-                    }),
+            .find(|(n, _)| *n == name)
+            .map(|(_, filename)| filename.to_string())
+            .unwrap();
+        match filename.strip_suffix("-Regular.ttf") {
+            Some(stem) => (stem.to_string(), options.format.extension()),
+            // emoji
+            None => return Ok(vec![filename]),
+        }
+    } else {
+        (name.replace([' ', '-'], ""), options.format.extension())
+    };
+    Ok(if variable {
+        let italic = if style.italic { "-Italic" } else { "" };
+        ["wght", "wdth,wght"]
+            .iter()
+            .map(|axes| format!("{stem}{italic}[{axes}].{ext}"))
+            .collect_vec()
+    } else {
+        vec![format!("{stem}-{suffix}.{ext}")]
+    })
+}
+
+/// The family name inside the font file, which isn't always the name in the overview data, e.g.
+/// `Noto Sans Imperial Aramaic` for `Noto Sans ImpAramaic` or `Noto Sans Old Persian` for
+/// `Noto Sans OldPersian`
+fn family_name(name: &str) -> String {
+    // real families that are written in camel case
+    const CAMEL: [&str; 2] = ["PhagsPa", "SignWriting"];
+    if name.contains("CJK") {
+        return name.to_string();
+    }
+    let stem = match SPECIAL_FILENAMES.iter().find(|(n, _)| *n == name) {
+        Some((_, filename)) => filename.split(['-', '.']).next().unwrap_or(filename),
+        None => name,
+    };
+    stem.split(' ')
+        .map(|word| {
+            if CAMEL.contains(&word) {
+                return word.to_string();
+            }
+            let mut split = String::new();
+            let mut lower = false;
+            for c in word.chars() {
+                if c.is_uppercase() && lower {
+                    split.push(' ');
                 }
-            })
-            .collect()
+                lower = c.is_lowercase();
+                split.push(c);
+            }
+            split
+        })
+        .join(" ")
+}
+
+impl FontStack {
+    /// Gets the font files from GitHub
+    pub fn files(&self) -> Result<Vec<Font>, NotoizeError> {
+        self.files_from(&FontSources::default())
+    }
+
+    /// Gets the font files from `source`, e.g. a [`LocalFonts`] or a [`FontSources`] chain
+    pub fn files_from(&self, source: &impl FontSource) -> Result<Vec<Font>, NotoizeError> {
+        Ok(self.fetch_files(source, &FileOptions::new(), true)?.fonts)
+    }
+
+    /// Gets the font files in every style asked for. Styles that a font doesn't come in end up in
+    /// [`FontFiles::missing`] rather than being an error
+    pub fn files_with(&self, options: &FileOptions) -> Result<FontFiles, NotoizeError> {
+        self.fetch_files(options.source.as_ref(), options, false)
     }
 
-    pub fn map_string(&self) -> MapString {
-        fn stringify(stuff: &[String]) -> String {
-            stuff
+    /// Gets the files from `source` rather than `options.source`. With `strict`, a missing file is
+    /// an error
+    fn fetch_files(
+        &self,
+        source: &dyn FontSource,
+        options: &FileOptions,
+        strict: bool,
+    ) -> Result<FontFiles, NotoizeError> {
+        let variable = options.variable;
+        let styles = if variable {
+            // one file has all the weights
+            options
+                .styles
                 .iter()
-                .sorted_by_cached_key(|f| script(f).0.to_lowercase())
-                .group_by(|f| script(f).0.to_lowercase())
-                .into_iter()
-                .map(|(_, mut g)| g.join(", "))
-                .join("\r\n    ")
+                .map(|s| Style::new(Weight::Regular, s.italic))
+                .unique()
+                .collect_vec()
+        } else {
+            options.styles.clone()
+        };
+        // not every family has a variable build (emoji never does), so those get the static
+        // regular or italic instead
+        let fixed = FileOptions {
+            variable: false,
+            ..options.clone()
+        };
+        let mut fonts = vec![];
+        let mut missing = vec![];
+        for x in &self.names {
+            'style: for style in styles.iter().copied() {
+                // there's only one emoji font
+                if x.contains("Emoji") && style != Style::REGULAR {
+                    missing.push((x.clone(), style));
+                    continue;
+                }
+                eprintln!("\x1b[92mfetching\x1b[m {x} {}", style.suffix());
+                let statics = filenames(x, style, &fixed)?;
+                let candidates = match variable {
+                    true => filenames(x, style, options)?
+                        .into_iter()
+                        .filter(|f| !statics.contains(f))
+                        .map(|f| (f, true))
+                        .collect_vec(),
+                    false => vec![],
+                };
+                let mut err = None;
+                for (filename, variable) in candidates
+                    .into_iter()
+                    .chain(statics.into_iter().map(|f| (f, false)))
+                {
+                    let spec = FontSpec {
+                        fontname: x.to_string(),
+                        filename,
+                        style,
+                        variable,
+                        format: options.format,
+                    };
+                    match source.fetch(&spec) {
+                        Ok(bytes) => {
+                            fonts.push(Font {
+                                axes: Axis::read(&bytes),
+                                bytes,
+                                filename: spec.filename,
+                                fontname: spec.fontname,
+                                style: spec.style,
+                            });
+                            continue 'style;
+                        }
+                        Err(e @ NotoizeError::MissingFile(_)) => {
+                            err.get_or_insert(e);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+                match err {
+                    Some(e) if strict => return Err(e),
+                    _ => {
+                        eprintln!("\x1b[93mmissing\x1b[m {x} {}", style.suffix());
+                        missing.push((x.clone(), style));
+                    }
+                }
+            }
         }
-        let mut all = String::new();
-        let mut conflicts = String::new();
-        let mut missing = String::new();
-        for (c, fonts) in self
-            .map
+        Ok(FontFiles { fonts, missing })
+    }
+
+    /// Which font in the stack renders `c`, or `None` if it isn't in the input or nothing covers it.
+    /// Controls and default-ignorable characters like ZWJ are also `None`, since they go with
+    /// whatever font their neighbours use
+    pub fn font_for(&self, c: char) -> Option<&str> {
+        self.assignment.get(&(c as u32)).map(|f| f.as_str())
+    }
+
+    /// The codepoints in the input that `font` renders, sorted. The name can be given with or
+    /// without the `Noto ` prefix
+    pub fn codepoints_for(&self, font: &str) -> Vec<u32> {
+        let font = match font.starts_with("Noto ") {
+            true => font.to_string(),
+            false => format!("Noto {font}"),
+        };
+        self.assignment
             .iter()
-            .filter(|m| !m.1.is_empty())
+            .filter(|(_, f)| **f == font)
+            .map(|(c, _)| *c)
+            .sorted()
             .collect_vec()
+    }
+
+    /// Codepoints in the input that no Noto font covers, grouped by block. Codepoints outside of
+    /// every block are grouped under `None`
+    pub fn uncovered(&self) -> Vec<(Option<BlockEndpoints>, Vec<u32>)> {
+        self.by_block(&self.uncovered)
+    }
+
+    /// Codepoints in the input that only fonts ruled out by the
+    /// [`NotoizeOptions`](crate::NotoizeOptions) cover, grouped the same way as
+    /// [`uncovered`](Self::uncovered)
+    pub fn excluded(&self) -> Vec<(Option<BlockEndpoints>, Vec<u32>)> {
+        self.by_block(&self.excluded)
+    }
+
+    fn by_block(&self, codepoints: &[u32]) -> Vec<(Option<BlockEndpoints>, Vec<u32>)> {
+        codepoints
+            .iter()
+            .group_by(|c| self.blocks.iter().find(|b| b.start <= **c && **c <= b.end))
             .into_iter()
-            .sorted()
-        {
-            let fonts_str = stringify(fonts);
-            let entry = &format!("{c:04x}\r\n    {fonts_str}\r\n");
-            all += entry;
-            if scripts(fonts).len() > 1 {
-                conflicts += entry;
-            }
-            let bad = missing_variants(fonts);
-            if !bad.is_empty() {
-                missing += &format!("{c:04x}\r\n    {}\r\n", stringify(&bad));
-            }
-        }
-        MapString {
-            all,
-            conflicts,
-            missing,
-        }
+            .map(|(b, g)| (b.cloned(), g.copied().collect_vec()))
+            .collect_vec()
+    }
+
+    /// [`report`](Self::report) as text
+    pub fn map_string(&self) -> Result<MapString, NotoizeError> {
+        Ok(self.report().to_text())
     }
 }
 
@@ -187,55 +372,180 @@ pub struct CodepointFontSupport {
     fonts: Option<Vec<String>>,
 }
 
-#[derive(Clone, Deserialize, Debug, PartialEq)]
-struct BlockEndpoints {
+/// A Unicode block, as listed in the overview repo's `blocks.json`
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct BlockEndpoints {
     ix: usize,
-    start: u32,
-    end: u32,
-    name: String,
+    pub start: u32,
+    pub end: u32,
+    pub name: String,
 }
 
 #[derive(Clone)]
 pub struct NotoizeClient {
+    source: Arc<dyn CoverageSource>,
     blocks: Vec<BlockEndpoints>,
     font_support: HashMap<u32, Vec<String>>,
 }
 
-impl Default for NotoizeClient {
-    fn default() -> Self {
-        Self::new()
+impl NotoizeClient {
+    /// Uses the overview repo on GitHub for coverage data
+    pub fn new() -> Result<Self, NotoizeError> {
+        Self::with_source(GithubCoverage)
     }
-}
 
-impl NotoizeClient {
-    pub fn new() -> Self {
-        Self {
+    /// Uses the coverage snapshot compiled into the crate, so no network access is needed
+    #[cfg(feature = "embedded-data")]
+    pub fn embedded() -> Result<Self, NotoizeError> {
+        Self::with_source(EmbeddedCoverage)
+    }
+
+    /// Uses `source` for coverage data instead, e.g. a [`LocalCoverage`] pointing at a checkout of
+    /// the overview repo
+    pub fn with_source(source: impl CoverageSource + 'static) -> Result<Self, NotoizeError> {
+        Ok(Self {
             blocks: {
                 eprintln!("\x1b[92mfetching\x1b[m block list");
-                serde_json::from_slice::<Vec<BlockEndpoints>>(
-                    &fetch("notofonts", "overview", &["blocks.json"]).unwrap().0[0]
-                        .content
-                        .clone(),
-                )
-                .unwrap()
+                serde_json::from_slice::<Vec<BlockEndpoints>>(&source.fetch("blocks.json")?)?
             },
+            source: Arc::new(source),
             font_support: HashMap::new(),
-        }
+        })
+    }
+
+    /// Returns a minimal font stack for rendering `text`. Controls and default-ignorable characters
+    /// (ZWJ, variation selectors, bidi controls, soft hyphens, ...) don't count, since they don't
+    /// need a font of their own
+    pub fn notoize(&mut self, text: &str) -> Result<FontStack, NotoizeError> {
+        self.notoize_with(text, &NotoizeOptions::default())
     }
 
-    /// Returns a minimal font stack for rendering `text`
-    pub fn notoize(&mut self, text: &str) -> FontStack {
-        let codepoints = text
+    /// Same as [`notoize`](Self::notoize), but with control over which fonts get picked
+    pub fn notoize_with(
+        &mut self,
+        text: &str,
+        options: &NotoizeOptions,
+    ) -> Result<FontStack, NotoizeError> {
+        let input = text
             .chars()
             .map(|c| c as u32)
             .sorted()
             .dedup()
             .collect_vec();
+        // ignorables still get loaded, so their blocks show up in `map` and reports
+        self.load(&input)?;
+        let codepoints = input
+            .iter()
+            .copied()
+            .filter(|c| !ignorable(*c))
+            .collect_vec();
+        let preferred = options.preferred(&codepoints);
+        let map = self
+            .font_support
+            .iter()
+            .map(|(c, f)| (*c, options.rank(f, &preferred)))
+            .collect::<HashMap<_, _>>();
+        let chosen = options.minimizes().then(|| {
+            cover::minimal_cover(
+                &codepoints
+                    .iter()
+                    .filter_map(|c| map.get(c).map(|f| f.as_slice()))
+                    .collect_vec(),
+            )
+        });
         let mut fonts = Vec::with_capacity(codepoints.len());
-        let mut data = BlockData {
-            cps: HashMap::new(),
-            fonts: None,
-        };
+        let mut assignment = HashMap::new();
+        // characters shared between scripts go last, so they can go with whatever the rest of the
+        // text is using
+        let (specific, shared): (Vec<_>, Vec<_>) = codepoints.iter().partition(|c| {
+            let script = char::from_u32(**c).map(|c| c.script());
+            !matches!(script, Some(Ucd::Common | Ucd::Inherited))
+        });
+        for c in specific.into_iter().chain(shared) {
+            let candidates = map
+                .get(c)
+                .into_iter()
+                .flatten()
+                .filter(|f| chosen.as_ref().is_none_or(|chosen| chosen.contains(f)))
+                .collect_vec();
+            let scx = char::from_u32(*c).map(|c| c.script_extension());
+            let used = |f: &&String| fonts.contains(&format!("Noto {f}"));
+            let native = |f: &&String| scx.is_some_and(|scx| in_scripts(f, scx));
+            let sel = candidates
+                .iter()
+                .find(|f| used(f) && native(f))
+                .or_else(|| candidates.iter().find(|f| used(f)))
+                .or_else(|| candidates.iter().find(|f| native(f)))
+                .or(candidates.first());
+            if let Some(sel) = sel {
+                let name = format!("Noto {sel}");
+                if !fonts.contains(&name) {
+                    eprintln!("\x1b[96mneed\x1b[m {sel} for u+{c:04x}");
+                    fonts.push(name.clone());
+                }
+                assignment.insert(*c, name);
+            }
+        }
+        // ignorables don't need a font of their own, but the font they go with still has to get
+        // them, e.g. the zwj in the middle of an emoji sequence
+        let chars = text
+            .chars()
+            .filter(|c| !c.is_control())
+            .map(|c| c as u32)
+            .collect_vec();
+        let mut next = vec![None; chars.len()];
+        for i in (0..chars.len().saturating_sub(1)).rev() {
+            next[i] = match ignorable(chars[i + 1]) {
+                true => next[i + 1],
+                false => Some(chars[i + 1]),
+            };
+        }
+        let mut ignorables: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut prev = None;
+        for (c, next) in chars.iter().zip(next) {
+            if !ignorable(*c) {
+                prev = Some(*c);
+                continue;
+            }
+            let (before, after) = attaches_to(*c);
+            for font in [prev.filter(|_| before), next.filter(|_| after)]
+                .iter()
+                .flatten()
+                .filter_map(|c| assignment.get(c))
+            {
+                let around = ignorables.entry(*c).or_default();
+                if !around.contains(font) {
+                    around.push(font.clone());
+                }
+            }
+        }
+        Ok(FontStack {
+            names: fonts,
+            input_map: input
+                .iter()
+                .filter_map(|c| map.get(c).map(|f| (*c, f.clone())))
+                .collect(),
+            assignment,
+            ignorables,
+            uncovered: codepoints
+                .iter()
+                .copied()
+                .filter(|c| self.font_support.get(c).is_none_or(|f| f.is_empty()))
+                .collect_vec(),
+            excluded: codepoints
+                .into_iter()
+                .filter(|c| {
+                    self.font_support.get(c).is_some_and(|f| !f.is_empty())
+                        && map.get(c).is_none_or(|f| f.is_empty())
+                })
+                .collect_vec(),
+            map,
+            blocks: self.blocks.clone(),
+        })
+    }
+
+    /// Makes sure `font_support` has every block that `codepoints` (sorted) touch
+    fn load(&mut self, codepoints: &[u32]) -> Result<(), NotoizeError> {
         let mut old_block = None;
         for (i, c) in codepoints.iter().enumerate() {
             // blocks can only start at u+xxxxx0
@@ -243,46 +553,36 @@ impl NotoizeClient {
                 continue;
             }
             let block = self.blocks.iter().find(|b| b.start <= *c && *c <= b.end);
-            if block != old_block {
-                if let Some(i) = block.map(|b| b.ix) {
-                    let path = format!("blocks/block-{i:03}.json");
-                    let block = block.unwrap();
-                    let e = {
-                        if !self.font_support.contains_key(c) {
-                            eprintln!(
-                                "\x1b[92mfetching\x1b[m {:04x}-{:04x} {}",
-                                block.start, block.end, block.name
-                            );
-                            data = serde_json::from_slice::<BlockData>(
-                                &fetch("notofonts", "overview", &[&path]).unwrap().0[0]
-                                    .content
-                                    .clone(),
-                            )
-                            .unwrap();
-                        }
-                        &data
-                    };
-                    let formatted = e
+            if block != old_block && !self.font_support.contains_key(c) {
+                if let Some(block) = block {
+                    let path = format!("blocks/block-{:03}.json", block.ix);
+                    eprintln!(
+                        "\x1b[92mfetching\x1b[m {:04x}-{:04x} {}",
+                        block.start, block.end, block.name
+                    );
+                    let data = serde_json::from_slice::<BlockData>(&self.source.fetch(&path)?)?;
+                    let mut unknown = vec![];
+                    let formatted = data
                         .cps
                         .iter()
                         .map(|(k, v)| {
-                            (
-                                k.parse::<u32>().unwrap(),
-                                match &e.fonts {
-                                    None => v.fonts.clone().unwrap_or(vec![]),
-                                    Some(f) => f.to_vec(),
-                                }
-                                .iter()
-                                .filter(|f| !["UI", "Display"].iter().any(|a| f.contains(a)))
-                                .filter(|f| {
-                                    script(f);
-                                    true
-                                })
-                                .cloned()
-                                .collect_vec(),
-                            )
+                            let fonts = match &data.fonts {
+                                None => v.fonts.clone().unwrap_or(vec![]),
+                                Some(f) => f.to_vec(),
+                            };
+                            unknown.extend(fonts.iter().filter(|f| !script(f).is_known()).cloned());
+                            Ok((
+                                k.parse::<u32>()
+                                    .map_err(|e| NotoizeError::Parse(format!("`{k}`: {e}")))?,
+                                fonts,
+                            ))
                         })
-                        .collect::<HashMap<_, _>>();
+                        .collect::<Result<HashMap<_, _>, NotoizeError>>()?;
+                    for f in unknown.iter().sorted().dedup() {
+                        eprintln!(
+                            "\x1b[93munknown\x1b[m {f} - it'll still be used but isn't classified"
+                        );
+                    }
                     let v = vec![];
                     for c in block.start..=block.end {
                         let insert = formatted.get_key_value(&c).unwrap_or((&c, &v));
@@ -292,46 +592,33 @@ impl NotoizeClient {
             }
             old_block = block;
         }
-        let font_support = &self.font_support;
-        for (c, f) in codepoints
-            .iter()
-            .filter(|c| !font_support.get(c).unwrap_or(&vec![]).is_empty())
-            .map(|c| (c, font_support.get(c)))
-        {
-            let f = f
-                .unwrap()
-                .iter()
-                .map(|e| e.to_string())
-                .sorted_by_key(|e| (!e.contains("Sans"), e.clone()))
-                .collect_vec();
-            if let Some(sel) = f.first() {
-                if !fonts.contains(&format!("Noto {sel}")) {
-                    eprintln!("\x1b[96mneed\x1b[m {sel} for u+{c:04x}");
-                    fonts.push(format!("Noto {sel}"));
-                }
-            }
-        }
-        FontStack {
-            names: fonts,
-            map: font_support.clone(),
-        }
+        Ok(())
     }
 }
 
 macro_rules! generate_script {
     ($($($font:literal)|* => $script:literal),* $(,)?) => {
-        #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-        pub struct Script(String);
+        #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub enum Script {
+            Known(String),
+            /// A font that's newer than this version of the crate - please file an issue on the
+            /// github repo or i'll catch it in up to three months
+            Unknown(String),
+        }
         pub fn script(font: &str) -> Script {
             $(
             if let $($font)|* = font {
-                return Script($script.to_string());
+                return Script::Known($script.to_string());
             }
             )*
-            panic!(
-                "unknown font name `\x1b[91m{font}\x1b[m` - please file an issue on the github repo or i'll catch \
-                 it in up to three months"
-            )
+            // UI and Display families are the same script as the regular one
+            match font.strip_suffix(" UI").or_else(|| font.strip_suffix(" Display")) {
+                Some(base) if !base.is_empty() => match script(base) {
+                    Script::Known(s) => Script::Known(s),
+                    Script::Unknown(_) => Script::Unknown(font.to_string()),
+                },
+                _ => Script::Unknown(font.to_string()),
+            }
         }
         pub fn all_variants() -> Vec<String> {
             let mut a = vec![];
@@ -522,20 +809,305 @@ generate_script! {
     "Znamenny Musical Notation" => "Znamenny Musical Notation",
 }
 
+impl Script {
+    /// The script's name, or the font's name if it's [`Script::Unknown`]
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Known(s) | Self::Unknown(s) => s,
+        }
+    }
+
+    pub fn is_known(&self) -> bool {
+        matches!(self, Self::Known(_))
+    }
+}
+
 pub fn scripts(fonts: &[String]) -> Vec<Script> {
     fonts
         .iter()
-        .map(|f| f.as_str())
-        .map(script)
-        .sorted_by_key(|f| f.0.to_lowercase())
+        .map(|f| script(f))
+        .sorted_by_key(|f| f.name().to_lowercase())
         .dedup()
         .collect_vec()
 }
 
+/// Writes a map in codepoint order, so the same stack always comes out the same
+fn sorted<S: Serializer, V: Serialize>(map: &HashMap<u32, V>, s: S) -> Result<S::Ok, S::Error> {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(s)
+}
+
+/// Control characters and Default_Ignorable_Code_Point - nothing needs a font to draw these, so
+/// they go with whatever font is around them
+fn ignorable(c: u32) -> bool {
+    matches!(
+        c,
+        0x0000..=0x001f
+            | 0x007f..=0x009f
+            | 0x00ad
+            | 0x034f
+            | 0x061c
+            | 0x115f..=0x1160
+            | 0x17b4..=0x17b5
+            | 0x180b..=0x180f
+            | 0x200b..=0x200f
+            | 0x202a..=0x202e
+            | 0x2060..=0x206f
+            | 0x3164
+            | 0xfe00..=0xfe0f
+            | 0xfeff
+            | 0xffa0
+            | 0xfff0..=0xfff8
+            | 0x1bca0..=0x1bca3
+            | 0x1d173..=0x1d17a
+            | 0xe0000..=0xe0fff
+    )
+}
+
+/// Whether ignorable `c` goes with the character before it and the one after it. Variation
+/// selectors and tags modify what's before them, and joiners are drawn by what comes next
+fn attaches_to(c: u32) -> (bool, bool) {
+    match c {
+        0x180b..=0x180d | 0x180f | 0xfe00..=0xfe0f | 0xe0000..=0xe007f | 0xe0100..=0xe01ef => {
+            (true, false)
+        }
+        0x034f | 0x200c | 0x200d | 0x2060 => (false, true),
+        _ => (true, true),
+    }
+}
+
+/// Whether `font` is for one of the scripts in `scx`. Common and Inherited don't count
+fn in_scripts(font: &str, scx: ScriptExtension) -> bool {
+    let ours = script(font);
+    let name = ours.name().replace([' ', '-'], "").to_lowercase();
+    !scx.is_common()
+        && !scx.is_inherited()
+        && scx.iter().any(|s| match ours {
+            Script::Known(_) if name == "cjk" => {
+                matches!(
+                    s,
+                    Ucd::Han | Ucd::Hiragana | Ucd::Katakana | Ucd::Hangul | Ucd::Bopomofo
+                )
+            }
+            Script::Known(_) => s.full_name().replace('_', "").to_lowercase() == name,
+            Script::Unknown(_) => false,
+        })
+}
+
 fn missing_variants(font_names: &[String]) -> Vec<String> {
+    let scripts = scripts(font_names);
     all_variants()
-        .iter()
-        .filter(|v| font_names.iter().any(|f| script(f) == script(v)) && !font_names.contains(v))
-        .cloned()
+        .into_iter()
+        .filter(|v| scripts.contains(&script(v)) && !font_names.contains(v))
         .collect_vec()
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// A start, end, name and the fonts for each codepoint that has any
+    type Block = (u32, u32, &'static str, Vec<(u32, Vec<&'static str>)>);
+
+    /// Coverage data with just these blocks
+    struct FakeCoverage(Vec<Block>);
+
+    impl CoverageSource for FakeCoverage {
+        fn fetch(&self, path: &str) -> Result<Vec<u8>, NotoizeError> {
+            let data = match path {
+                "blocks.json" => json!(self
+                    .0
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, (start, end, name, _))| {
+                        json!({ "ix": ix, "start": start, "end": end, "name": name })
+                    })
+                    .collect_vec()),
+                _ => {
+                    let ix = path
+                        .trim_start_matches("blocks/block-")
+                        .trim_end_matches(".json")
+                        .parse::<usize>()
+                        .map_err(|_| NotoizeError::MissingFile(path.to_string()))?;
+                    let cps = self.0[ix]
+                        .3
+                        .iter()
+                        .map(|(c, fonts)| (c.to_string(), json!({ "fonts": fonts })))
+                        .collect::<serde_json::Map<_, _>>();
+                    json!({ "cps": cps, "fonts": null })
+                }
+            };
+            Ok(data.to_string().into_bytes())
+        }
+    }
+
+    /// A client that knows about `A`, U+0301, the astronaut emoji's halves and VS16. Nothing else
+    /// is covered
+    pub(crate) fn client() -> NotoizeClient {
+        NotoizeClient::with_source(FakeCoverage(vec![
+            (
+                0x0,
+                0x7f,
+                "Basic Latin",
+                vec![(0x41, vec!["Sans", "Serif"])],
+            ),
+            (
+                0x300,
+                0x36f,
+                "Combining Diacritical Marks",
+                vec![(0x301, vec!["Sans", "Serif"])],
+            ),
+            (0x2000, 0x206f, "General Punctuation", vec![]),
+            (
+                0xfe00,
+                0xfe0f,
+                "Variation Selectors",
+                vec![(0xfe0f, vec!["Color Emoji", "Sans Symbols 2"])],
+            ),
+            (
+                0x1f300,
+                0x1f6ff,
+                "Emoji",
+                vec![
+                    (0x1f468, vec!["Color Emoji"]),
+                    (0x1f680, vec!["Color Emoji"]),
+                ],
+            ),
+        ]))
+        .unwrap()
+    }
+
+    /// Has just these files, all empty
+    struct StubFonts(&'static [&'static str]);
+
+    impl FontSource for StubFonts {
+        fn fetch(&self, font: &FontSpec) -> Result<Vec<u8>, NotoizeError> {
+            match self.0.contains(&font.filename.as_str()) {
+                true => Ok(vec![]),
+                false => Err(NotoizeError::MissingFile(font.filename.clone())),
+            }
+        }
+    }
+
+    #[test]
+    fn variable_falls_back_to_static() {
+        let stack = client().notoize("A\u{1f468}").unwrap();
+        let options = FileOptions::new()
+            .variable(true)
+            .styles([Style::REGULAR, Style::BOLD])
+            .source(StubFonts(&[
+                "NotoSans[wdth,wght].ttf",
+                "NotoColorEmoji.ttf",
+            ]));
+        let files = stack.files_with(&options).unwrap();
+        assert_eq!(
+            files
+                .fonts
+                .iter()
+                .map(|f| f.filename.as_str())
+                .collect_vec(),
+            ["NotoSans[wdth,wght].ttf", "NotoColorEmoji.ttf"]
+        );
+        assert!(files.missing.is_empty());
+        let options = options.source(StubFonts(&["NotoSans-Regular.ttf"]));
+        let files = stack.files_with(&options).unwrap();
+        assert_eq!(files.fonts[0].filename, "NotoSans-Regular.ttf");
+        assert_eq!(
+            files.missing,
+            [("Noto Color Emoji".to_string(), Style::REGULAR)]
+        );
+    }
+
+    #[cfg(feature = "embedded-data")]
+    #[test]
+    fn embedded_data_loads() {
+        let stack = NotoizeClient::embedded().unwrap().notoize("A").unwrap();
+        assert_eq!(stack.names, ["Noto Sans"]);
+    }
+
+    #[test]
+    fn ignorables() {
+        for c in [
+            0x0a, 0x7f, 0x85, 0xad, 0x200d, 0x2060, 0xfe0f, 0xfeff, 0xe0067, 0xe007f,
+        ] {
+            assert!(ignorable(c), "u+{c:04x}");
+        }
+        for c in [0x20, 0x41, 0xa0, 0x2010, 0x20e3, 0xfe10, 0x1f3fb, 0x1f468] {
+            assert!(!ignorable(c), "u+{c:04x}");
+        }
+    }
+
+    #[test]
+    fn ignorables_dont_add_fonts() {
+        let stack = client().notoize("\u{200d}\u{fe0f}\n").unwrap();
+        assert!(stack.names.is_empty());
+        assert!(stack.uncovered.is_empty());
+        assert!(stack.ignorables.is_empty());
+    }
+
+    #[test]
+    fn ignorables_still_get_loaded() {
+        let stack = client().notoize("A\u{1f468}\u{fe0f}").unwrap();
+        assert_eq!(
+            stack.map[&0xfe0f].iter().sorted().collect_vec(),
+            ["Color Emoji", "Sans Symbols 2"]
+        );
+        assert!(stack.input_map.contains_key(&0xfe0f));
+        assert!(!stack.names.contains(&"Noto Sans Symbols 2".to_string()));
+        assert_eq!(stack.report().entries[1].codepoint, 0xfe0f);
+    }
+
+    #[test]
+    fn ignorables_only_go_with_what_they_modify() {
+        let stack = client()
+            .notoize("\u{1f468}\u{200d}\u{1f680}\u{fe0f}A\u{200d}\u{1f468}")
+            .unwrap();
+        assert_eq!(stack.ignorables[&0xfe0f], ["Noto Color Emoji"]);
+        assert_eq!(stack.ignorables[&0x200d], ["Noto Color Emoji"]);
+        let stack = client().notoize("A\u{fe0f}\u{1f468}").unwrap();
+        assert_eq!(stack.ignorables[&0xfe0f], ["Noto Sans"]);
+    }
+
+    #[test]
+    fn ignorables_go_with_their_neighbours() {
+        let stack = client()
+            .notoize("A \u{1f468}\u{200d}\u{1f680}\u{fe0f}")
+            .unwrap();
+        assert_eq!(stack.names, ["Noto Sans", "Noto Color Emoji"]);
+        assert_eq!(stack.font_for('\u{200d}'), None);
+        assert_eq!(stack.ignorables[&0x200d], ["Noto Color Emoji"]);
+        assert_eq!(stack.ignorables[&0xfe0f], ["Noto Color Emoji"]);
+        assert_eq!(stack.uncovered, [0x20]);
+    }
+
+    #[test]
+    fn cjk_has_no_italics() {
+        for variable in [false, true] {
+            let options = FileOptions::new().variable(variable);
+            assert!(filenames("Noto Sans CJK JP", Style::ITALIC, &options)
+                .unwrap()
+                .is_empty());
+            assert!(!filenames("Noto Sans CJK JP", Style::REGULAR, &options)
+                .unwrap()
+                .is_empty());
+        }
+    }
+
+    #[test]
+    fn family_names() {
+        for (name, family) in [
+            ("Noto Sans", "Noto Sans"),
+            ("Noto Sans ImpAramaic", "Noto Sans Imperial Aramaic"),
+            ("Noto Sans OldPersian", "Noto Sans Old Persian"),
+            ("Noto Sans PauCinHau", "Noto Sans Pau Cin Hau"),
+            ("Noto Sans WarangCiti", "Noto Sans Warang Citi"),
+            ("Noto Sans PhagsPa", "Noto Sans PhagsPa"),
+            ("Noto Sans NKo", "Noto Sans NKo"),
+            ("Noto Fangsong KSS Rotated", "Noto Fangsong KSS Rotated"),
+            ("Noto Sans CJK JP", "Noto Sans CJK JP"),
+            ("Noto Color Emoji", "Noto Color Emoji"),
+        ] {
+            assert_eq!(family_name(name), family);
+        }
+    }
+}
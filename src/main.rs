@@ -3,19 +3,46 @@ use std::{fs, sync::LazyLock, time::Instant};
 
 fn main() {
     let start = Instant::now();
-    let mut client = NotoizeClient::new();
+    let snapshot = SnapshotCoverage::build(&GithubCoverage).unwrap();
+    let mut client =
+        NotoizeClient::with_source(SnapshotCoverage::from_bytes(&snapshot).unwrap()).unwrap();
     static ALL: LazyLock<String> =
         LazyLock::new(|| (0..0x110000).filter_map(char::from_u32).collect::<String>());
-    let the = client.notoize(&ALL);
-    let map = the.map_string();
+    let the = client.notoize(&ALL).unwrap();
+    let report = the.report();
+    let map = report.to_text();
     let _ = fs::remove_dir_all("out/data");
     fs::create_dir_all("out/data").unwrap();
     fs::write("out/data/mapping.txt", map.all).unwrap();
     fs::write("out/data/script_conflicts.txt", map.conflicts).unwrap();
     fs::write("out/data/missing_variants.txt", map.missing).unwrap();
+    for (name, report) in [
+        ("mapping", report.clone()),
+        ("script_conflicts", report.conflicts()),
+        ("missing_variants", report.missing()),
+    ] {
+        fs::write(format!("out/data/{name}.json"), report.to_json().unwrap()).unwrap();
+        fs::write(format!("out/data/{name}.csv"), report.to_csv()).unwrap();
+        fs::write(format!("out/data/{name}.tsv"), report.to_tsv()).unwrap();
+    }
+    let ranges = the.range_report();
+    let map = ranges.to_text();
+    fs::write("out/data/mapping_ranges.txt", map.all).unwrap();
+    fs::write("out/data/script_conflicts_ranges.txt", map.conflicts).unwrap();
+    fs::write("out/data/missing_variants_ranges.txt", map.missing).unwrap();
+    for (name, report) in [
+        ("mapping_ranges", ranges.clone()),
+        ("script_conflicts_ranges", ranges.conflicts()),
+        ("missing_variants_ranges", ranges.missing()),
+    ] {
+        fs::write(format!("out/data/{name}.json"), report.to_json().unwrap()).unwrap();
+        fs::write(format!("out/data/{name}.csv"), report.to_csv()).unwrap();
+        fs::write(format!("out/data/{name}.tsv"), report.to_tsv()).unwrap();
+    }
+    fs::write("out/data/coverage.json.gz", snapshot).unwrap();
     let _ = fs::remove_dir_all("out/fonts");
     fs::create_dir_all("out/fonts").unwrap();
-    for font in the.files() {
+    for font in the.files().unwrap() {
         fs::write(format!("out/fonts/{}", font.filename), font.bytes).unwrap();
     }
     println!("\x1b[92m{:?}\x1b[m", start.elapsed());
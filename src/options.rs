@@ -0,0 +1,278 @@
+use crate::{lang, script, FontFormat, FontSource, FontSources, Style};
+use itertools::Itertools;
+use std::sync::Arc;
+
+/// Which kind of family to pick when a character is covered by more than one
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Typeface {
+    #[default]
+    Sans,
+    Serif,
+    Mono,
+}
+
+/// Controls which fonts [`NotoizeClient::notoize_with`](crate::NotoizeClient::notoize_with)
+/// picks
+#[derive(Debug, Clone, Default)]
+pub struct NotoizeOptions {
+    prefer: Typeface,
+    ui: bool,
+    display: bool,
+    allow: Vec<String>,
+    deny: Vec<String>,
+    minimize: bool,
+    lang: Option<String>,
+    variants: Vec<(String, String)>,
+}
+
+impl NotoizeOptions {
+    /// Sans, no UI or Display families, nothing allowed or denied - the same as
+    /// [`notoize`](crate::NotoizeClient::notoize)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn prefer(mut self, typeface: Typeface) -> Self {
+        self.prefer = typeface;
+        self
+    }
+
+    /// Whether to use UI families (e.g. `Sans Arabic UI`). They're preferred over the non-UI
+    /// version when included
+    pub fn ui(mut self, ui: bool) -> Self {
+        self.ui = ui;
+        self
+    }
+
+    /// Whether to use Display families (e.g. `Serif Display`). They're preferred over the
+    /// non-Display version when included
+    pub fn display(mut self, display: bool) -> Self {
+        self.display = display;
+        self
+    }
+
+    /// Only ever use these fonts. Names can be given with or without the `Noto ` prefix
+    pub fn allow<S: AsRef<str>>(mut self, fonts: impl IntoIterator<Item = S>) -> Self {
+        self.allow
+            .extend(fonts.into_iter().map(|f| unprefixed(f.as_ref())));
+        self
+    }
+
+    /// Never use these fonts. Names can be given with or without the `Noto ` prefix
+    pub fn deny<S: AsRef<str>>(mut self, fonts: impl IntoIterator<Item = S>) -> Self {
+        self.deny
+            .extend(fonts.into_iter().map(|f| unprefixed(f.as_ref())));
+        self
+    }
+
+    /// Instead of taking each character's favourite font in order, find the smallest set of fonts
+    /// that covers everything (preferences only break ties). This is slower, and for big inputs it
+    /// settles for a very good stack rather than a provably minimal one
+    pub fn minimize(mut self, minimize: bool) -> Self {
+        self.minimize = minimize;
+        self
+    }
+
+    /// The BCP 47 language tag of the text (e.g. `ja`, `zh-Hant-HK` or `ur`), which picks the
+    /// right regional variant where there's a choice, like `Sans CJK JP` over `Sans CJK HK` or
+    /// `Nastaliq Urdu` over `Sans Arabic`. Without one, the CJK variant is guessed from the text:
+    /// kana means Japanese and hangul means Korean
+    pub fn lang(mut self, tag: impl Into<String>) -> Self {
+        self.lang = Some(tag.into());
+        self
+    }
+
+    /// Adds to the built-in table of which variant each language wants (see
+    /// [`language_variants`](crate::language_variants)), e.g. `.variant("ber-MA", "Sans Tifinagh
+    /// APT")`. The tag matches any [`lang`](Self::lang) that has all of its subtags, and takes
+    /// priority over built-in entries that are just as specific
+    pub fn variant(mut self, tag: impl Into<String>, font: impl AsRef<str>) -> Self {
+        self.variants.push((tag.into(), unprefixed(font.as_ref())));
+        self
+    }
+
+    /// The variants that `lang` or the text itself call for, one per script at most
+    pub(crate) fn preferred(&self, codepoints: &[u32]) -> Vec<String> {
+        let mut preferred = self
+            .lang
+            .as_deref()
+            .map(|l| lang::preferred_variants(l, &self.variants))
+            .unwrap_or_default();
+        for guess in lang::guessed_variants(codepoints) {
+            if !preferred.iter().any(|p| script(p) == script(&guess)) {
+                preferred.push(guess);
+            }
+        }
+        preferred
+    }
+
+    pub(crate) fn minimizes(&self) -> bool {
+        self.minimize
+    }
+
+    /// Whether `font` (without the `Noto ` prefix) may be used at all
+    pub(crate) fn permits(&self, font: &str) -> bool {
+        (self.ui || !font.contains("UI"))
+            && (self.display || !font.contains("Display"))
+            && (self.allow.is_empty() || self.allow.iter().any(|f| f == font))
+            && !self.deny.iter().any(|f| f == font)
+    }
+
+    /// The usable fonts out of `fonts`, best first. A `preferred` variant goes ahead of the other
+    /// fonts for its script, but not ahead of other scripts
+    pub(crate) fn rank(&self, fonts: &[String], preferred: &[String]) -> Vec<String> {
+        let mut ranked = fonts
+            .iter()
+            .filter(|f| self.permits(f))
+            .sorted_by_key(|f| self.sort_key(f))
+            .cloned()
+            .collect_vec();
+        for p in preferred {
+            if let Some(i) = ranked.iter().position(|f| f == p) {
+                let s = script(p);
+                if let Some(j) = ranked[..i].iter().position(|f| script(f) == s) {
+                    let f = ranked.remove(i);
+                    ranked.insert(j, f);
+                }
+            }
+        }
+        ranked
+    }
+
+    fn sort_key(&self, font: &str) -> (bool, bool, bool, bool, String) {
+        let preferred = match self.prefer {
+            Typeface::Sans => "Sans",
+            Typeface::Serif => "Serif",
+            Typeface::Mono => "Mono",
+        };
+        (
+            self.ui && !font.contains("UI"),
+            self.display && !font.contains("Display"),
+            !font.contains(preferred),
+            !font.contains("Sans"),
+            font.to_string(),
+        )
+    }
+}
+
+/// Controls what [`FontStack::files_with`](crate::FontStack::files_with) gets
+#[derive(Clone)]
+pub struct FileOptions {
+    pub(crate) source: Arc<dyn FontSource>,
+    pub(crate) styles: Vec<Style>,
+    pub(crate) variable: bool,
+    pub(crate) format: FontFormat,
+}
+
+impl Default for FileOptions {
+    fn default() -> Self {
+        Self {
+            source: Arc::new(FontSources::default()),
+            styles: vec![Style::REGULAR],
+            variable: false,
+            format: FontFormat::default(),
+        }
+    }
+}
+
+impl FileOptions {
+    /// Regular only, from GitHub - the same as [`files`](crate::FontStack::files)
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where to get the files from, e.g. a [`LocalFonts`](crate::LocalFonts)
+    pub fn source(mut self, source: impl FontSource + 'static) -> Self {
+        self.source = Arc::new(source);
+        self
+    }
+
+    /// Get variable fonts instead of static ones. Weights don't matter then - there's one file for
+    /// upright and one for italic, and each [`Font`](crate::Font) lists its [`Axis`](crate::Axis)es.
+    /// Families without a variable build (like emoji) get their static regular or italic instead
+    pub fn variable(mut self, variable: bool) -> Self {
+        self.variable = variable;
+        self
+    }
+
+    /// Which flavour of file to get, e.g. WOFF2 for the web or unhinted OTF for print. CJK fonts
+    /// only come as OTFs (or OTF, TTF and WOFF2 when variable) and emoji only as a TTF, so those
+    /// keep their own format
+    pub fn format(mut self, format: FontFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Which weights and styles to get for every font in the stack
+    pub fn styles(mut self, styles: impl IntoIterator<Item = Style>) -> Self {
+        self.styles = styles.into_iter().unique().collect();
+        self
+    }
+}
+
+/// The CSS `font-display` descriptor
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FontDisplay {
+    Auto,
+    Block,
+    #[default]
+    Swap,
+    Fallback,
+    Optional,
+}
+
+/// Controls what [`FontStack::to_css`](crate::FontStack::to_css) writes
+#[derive(Debug, Clone)]
+pub struct CssOptions {
+    pub(crate) url_prefix: String,
+    pub(crate) font_display: FontDisplay,
+    pub(crate) format: FontFormat,
+    pub(crate) selector: String,
+}
+
+impl Default for CssOptions {
+    fn default() -> Self {
+        Self {
+            url_prefix: String::new(),
+            font_display: FontDisplay::default(),
+            format: FontFormat::HintedWoff2,
+            selector: "body".to_string(),
+        }
+    }
+}
+
+impl CssOptions {
+    /// Hinted WOFF2s next to the CSS file, `font-display: swap`, and the `font-family` list on
+    /// `body`
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Goes in front of each filename, e.g. `/fonts` or `https://example.com/fonts/`
+    pub fn url_prefix(mut self, prefix: impl Into<String>) -> Self {
+        self.url_prefix = prefix.into();
+        self
+    }
+
+    pub fn font_display(mut self, display: FontDisplay) -> Self {
+        self.font_display = display;
+        self
+    }
+
+    /// Which files the URLs point to - should match what got passed to
+    /// [`FileOptions::format`]
+    pub fn format(mut self, format: FontFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Which rule gets the `font-family` list
+    pub fn selector(mut self, selector: impl Into<String>) -> Self {
+        self.selector = selector.into();
+        self
+    }
+}
+
+fn unprefixed(font: &str) -> String {
+    font.strip_prefix("Noto ").unwrap_or(font).to_string()
+}
@@ -0,0 +1,425 @@
+use crate::{missing_variants, script, BlockEndpoints, FontStack, MapString, NotoizeError, Script};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+/// Some fonts for the same script
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ScriptFonts {
+    pub script: Script,
+    /// Sorted, without the `Noto ` prefix
+    pub fonts: Vec<String>,
+}
+
+/// Which fonts cover one codepoint
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct ReportEntry {
+    pub codepoint: u32,
+    /// Known scripts in alphabetical order, then each unknown font on its own
+    pub scripts: Vec<ScriptFonts>,
+    /// Whether fonts for more than one known script cover it
+    pub conflict: bool,
+    /// Variants of those scripts that don't cover it, grouped the same way
+    pub missing_variants: Vec<ScriptFonts>,
+}
+
+/// Font coverage for every codepoint a [`FontStack`] knows about, in codepoint order
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CoverageReport {
+    pub entries: Vec<ReportEntry>,
+}
+
+impl FontStack {
+    pub fn report(&self) -> CoverageReport {
+        CoverageReport {
+            entries: self
+                .map
+                .iter()
+                .filter(|(_, fonts)| !fonts.is_empty())
+                .sorted_by_key(|(c, _)| **c)
+                .map(|(c, fonts)| {
+                    let scripts = grouped(fonts);
+                    ReportEntry {
+                        codepoint: *c,
+                        conflict: scripts.iter().filter(|s| s.script.is_known()).count() > 1,
+                        missing_variants: grouped(&missing_variants(fonts)),
+                        scripts,
+                    }
+                })
+                .collect_vec(),
+        }
+    }
+
+    /// [`report`](Self::report) with runs of codepoints that have the same fonts merged into
+    /// ranges, which is a lot shorter
+    pub fn range_report(&self) -> RangeReport {
+        self.report().ranges(&self.blocks)
+    }
+}
+
+impl CoverageReport {
+    /// Only the entries with a conflict
+    pub fn conflicts(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|e| e.conflict)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Only the entries with missing variants
+    pub fn missing(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|e| !e.missing_variants.is_empty())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// The same text as `out/data/*.txt`: each codepoint in hex, then a line per script
+    pub fn to_text(&self) -> MapString {
+        let text = |report: &Self| {
+            report
+                .entries
+                .iter()
+                .map(|e| text_entry(&format!("{:04x}", e.codepoint), &e.scripts))
+                .collect::<String>()
+        };
+        MapString {
+            all: text(self),
+            conflicts: text(&self.conflicts()),
+            missing: self
+                .missing()
+                .entries
+                .iter()
+                .map(|e| text_entry(&format!("{:04x}", e.codepoint), &e.missing_variants))
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, NotoizeError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// A row per codepoint and script: `codepoint,script,known,fonts,missing_variants,conflict`,
+    /// with the fonts separated by `;`
+    pub fn to_csv(&self) -> String {
+        self.to_table(',')
+    }
+
+    /// Same as [`to_csv`](Self::to_csv), but tab-separated
+    pub fn to_tsv(&self) -> String {
+        self.to_table('\t')
+    }
+
+    fn to_table(&self, sep: char) -> String {
+        table(
+            sep,
+            &["codepoint"],
+            self.entries.iter().map(|e| {
+                (
+                    vec![format!("{:04x}", e.codepoint)],
+                    &e.scripts[..],
+                    &e.missing_variants[..],
+                    e.conflict,
+                )
+            }),
+        )
+    }
+
+    /// Merges runs of consecutive codepoints in the same block with the same fonts
+    pub(crate) fn ranges(&self, blocks: &[BlockEndpoints]) -> RangeReport {
+        let block = |c: u32| blocks.iter().find(|b| b.start <= c && c <= b.end);
+        let mut entries: Vec<RangeEntry> = vec![];
+        for e in &self.entries {
+            let b = block(e.codepoint);
+            match entries.last_mut() {
+                Some(last)
+                    if last.end + 1 == e.codepoint
+                        && last.scripts == e.scripts
+                        && last.missing_variants == e.missing_variants
+                        && last.block.as_ref() == b.map(|b| &b.name) =>
+                {
+                    last.end = e.codepoint;
+                }
+                _ => entries.push(RangeEntry {
+                    start: e.codepoint,
+                    end: e.codepoint,
+                    block: b.map(|b| b.name.clone()),
+                    scripts: e.scripts.clone(),
+                    conflict: e.conflict,
+                    missing_variants: e.missing_variants.clone(),
+                }),
+            }
+        }
+        RangeReport { entries }
+    }
+}
+
+/// A run of consecutive codepoints that the same fonts cover
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RangeEntry {
+    pub start: u32,
+    /// Inclusive
+    pub end: u32,
+    /// The Unicode block, if it's in one - ranges never cross blocks
+    pub block: Option<String>,
+    pub scripts: Vec<ScriptFonts>,
+    pub conflict: bool,
+    pub missing_variants: Vec<ScriptFonts>,
+}
+
+/// A [`CoverageReport`] with runs of codepoints that have the same fonts merged into ranges
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RangeReport {
+    pub entries: Vec<RangeEntry>,
+}
+
+impl RangeReport {
+    /// Only the ranges with a conflict
+    pub fn conflicts(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|e| e.conflict)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Only the ranges with missing variants
+    pub fn missing(&self) -> Self {
+        Self {
+            entries: self
+                .entries
+                .iter()
+                .filter(|e| !e.missing_variants.is_empty())
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Like [`CoverageReport::to_text`], but each entry starts with `XXXX..YYYY` (or just `XXXX`)
+    /// and the block name
+    pub fn to_text(&self) -> MapString {
+        let label = |e: &RangeEntry| {
+            let range = match e.start == e.end {
+                true => format!("{:04x}", e.start),
+                false => format!("{:04x}..{:04x}", e.start, e.end),
+            };
+            match &e.block {
+                Some(block) => format!("{range} {block}"),
+                None => range,
+            }
+        };
+        let text = |report: &Self| {
+            report
+                .entries
+                .iter()
+                .map(|e| text_entry(&label(e), &e.scripts))
+                .collect::<String>()
+        };
+        MapString {
+            all: text(self),
+            conflicts: text(&self.conflicts()),
+            missing: self
+                .missing()
+                .entries
+                .iter()
+                .map(|e| text_entry(&label(e), &e.missing_variants))
+                .collect(),
+        }
+    }
+
+    pub fn to_json(&self) -> Result<String, NotoizeError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Like [`CoverageReport::to_csv`], but starting with `start,end,block`
+    pub fn to_csv(&self) -> String {
+        self.to_table(',')
+    }
+
+    /// Same as [`to_csv`](Self::to_csv), but tab-separated
+    pub fn to_tsv(&self) -> String {
+        self.to_table('\t')
+    }
+
+    fn to_table(&self, sep: char) -> String {
+        table(
+            sep,
+            &["start", "end", "block"],
+            self.entries.iter().map(|e| {
+                (
+                    vec![
+                        format!("{:04x}", e.start),
+                        format!("{:04x}", e.end),
+                        e.block.clone().unwrap_or_default(),
+                    ],
+                    &e.scripts[..],
+                    &e.missing_variants[..],
+                    e.conflict,
+                )
+            }),
+        )
+    }
+}
+
+/// `label`, then a line per script
+fn text_entry(label: &str, groups: &[ScriptFonts]) -> String {
+    let (known, unknown): (Vec<_>, Vec<_>) = groups.iter().partition(|g| g.script.is_known());
+    let lines = known
+        .iter()
+        .map(|g| g.fonts.join(", "))
+        .chain((!unknown.is_empty()).then(|| {
+            format!(
+                "unclassified: {}",
+                unknown.iter().flat_map(|g| &g.fonts).join(", ")
+            )
+        }))
+        .join("\r\n    ");
+    format!("{label}\r\n    {lines}\r\n")
+}
+
+/// A row per entry and script, starting with `key` columns
+fn table<'a>(
+    sep: char,
+    key: &[&str],
+    rows: impl Iterator<Item = (Vec<String>, &'a [ScriptFonts], &'a [ScriptFonts], bool)>,
+) -> String {
+    let field = |s: &str| match s.contains([sep, '"', '\n']) {
+        true => format!("\"{}\"", s.replace('"', "\"\"")),
+        false => s.to_string(),
+    };
+    let mut table = key
+        .iter()
+        .chain(&["script", "known", "fonts", "missing_variants", "conflict"])
+        .join(&sep.to_string())
+        + "\n";
+    for (key, scripts, missing_variants, conflict) in rows {
+        for g in scripts {
+            let missing = missing_variants
+                .iter()
+                .filter(|m| m.script == g.script)
+                .flat_map(|m| &m.fonts)
+                .join(";");
+            table += &key
+                .iter()
+                .map(|k| field(k))
+                .chain([
+                    field(g.script.name()),
+                    g.script.is_known().to_string(),
+                    field(&g.fonts.join(";")),
+                    field(&missing),
+                    conflict.to_string(),
+                ])
+                .join(&sep.to_string());
+            table += "\n";
+        }
+    }
+    table
+}
+
+/// Known scripts sorted by name, each with its fonts sorted, then the unknown fonts
+fn grouped(fonts: &[String]) -> Vec<ScriptFonts> {
+    let (known, unknown): (Vec<_>, Vec<_>) = fonts.iter().partition(|f| script(f).is_known());
+    known
+        .into_iter()
+        .sorted_by_cached_key(|f| (script(f).name().to_lowercase(), f.to_string()))
+        .group_by(|f| script(f).name().to_lowercase())
+        .into_iter()
+        .map(|(_, g)| {
+            let fonts = g.cloned().collect_vec();
+            ScriptFonts {
+                script: script(&fonts[0]),
+                fonts,
+            }
+        })
+        .chain(unknown.into_iter().map(|f| ScriptFonts {
+            script: script(f),
+            fonts: vec![f.clone()],
+        }))
+        .collect_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(codepoint: u32, fonts: &[&str]) -> ReportEntry {
+        let fonts = fonts.iter().map(|f| f.to_string()).collect_vec();
+        ReportEntry {
+            codepoint,
+            scripts: grouped(&fonts),
+            conflict: false,
+            missing_variants: vec![],
+        }
+    }
+
+    fn block(ix: usize, start: u32, end: u32, name: &str) -> BlockEndpoints {
+        BlockEndpoints {
+            ix,
+            start,
+            end,
+            name: name.to_string(),
+        }
+    }
+
+    #[test]
+    fn runs_merge_within_blocks() {
+        let report = CoverageReport {
+            entries: vec![
+                entry(0x41, &["Sans", "Serif"]),
+                entry(0x42, &["Sans", "Serif"]),
+                entry(0x43, &["Sans", "Serif"]),
+                // gap
+                entry(0x45, &["Sans", "Serif"]),
+                // different fonts
+                entry(0x46, &["Sans"]),
+                // same fonts, but the next block
+                entry(0x80, &["Sans"]),
+                // in no block
+                entry(0x100, &["Sans"]),
+            ],
+        };
+        let blocks = [
+            block(0, 0x0, 0x7f, "Basic Latin"),
+            block(1, 0x80, 0xff, "Latin-1 Supplement"),
+        ];
+        let ranges = report
+            .ranges(&blocks)
+            .entries
+            .into_iter()
+            .map(|e| (e.start, e.end, e.block))
+            .collect_vec();
+        let basic = Some("Basic Latin".to_string());
+        assert_eq!(
+            ranges,
+            [
+                (0x41, 0x43, basic.clone()),
+                (0x45, 0x45, basic.clone()),
+                (0x46, 0x46, basic),
+                (0x80, 0x80, Some("Latin-1 Supplement".to_string())),
+                (0x100, 0x100, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn range_text_labels() {
+        let report = CoverageReport {
+            entries: vec![entry(0x41, &["Sans"]), entry(0x42, &["Sans"])],
+        };
+        let text = report
+            .ranges(&[block(0, 0x0, 0x7f, "Basic Latin")])
+            .to_text();
+        assert_eq!(text.all, "0041..0042 Basic Latin\r\n    Sans\r\n");
+        assert!(text.conflicts.is_empty());
+    }
+}